@@ -0,0 +1,252 @@
+use super::*;
+
+/// Copies of a featured 5 star the exact analysis goes up to, a fully upgraded character or weapon
+const MAX_CHARACTER_COPIES: u32 = 7;
+const MAX_WEAPON_COPIES: u32 = 5;
+
+/// Exact distribution of the pulls needed for a featured 5 star, with what else drops on the way
+pub struct Exact5Star {
+    /// Probability of obtaining the target on each pull, from pull 0, by the Capturing
+    /// Radiance streak left after it, which the next copy starts from
+    pub pmf_by_streak: Vec<Vec<f64>>,
+    /// Chance of obtaining another featured 5 star before the target
+    pub other_featured: f64,
+    pub expected_other_featured: f64,
+    pub expected_off_banner: f64,
+}
+
+/// Exact distribution of the pulls needed for the featured 5 star `target` from the state of
+/// `banner`, by a Markov chain over the 5 star pity counter, the guarantee, the Capturing
+/// Radiance streak and the fate points of the Epitomized Path, which has to be charted on the
+/// target or not at all. `None` on banners it cannot model: scripted pulls, sparks, paths charted
+/// on another item, pity too long to keep a state for or a target taking too many pulls.
+pub fn featured_5star_pmf(banner: &GachaBanner, target: u32) -> Option<Exact5Star> {
+    let rules = &banner.rules;
+    let state = &banner.state;
+    let featured = rules.star5.featured.as_ref()?;
+    let max_5star = rules.star5.pity.max;
+    if !rules.script.is_empty() || rules.spark.is_some() || target >= featured.count || max_5star > MAX_EXACT_PITY {
+        return None;
+    }
+    let (path, points) = match (&rules.path, state.want_5star_state) {
+        (Some(path), WantState::Want(index, points)) if index == target => (Some(path), points.min(path.max_fate_points)),
+        (Some(_), WantState::Want(..)) => return None,
+        _ => (None, 0),
+    };
+    let max_points = path.map_or(0, |path| path.max_fate_points) as usize;
+    let streaks = featured.radiance.as_ref().map_or(1, |radiance| radiance.probabilities.len().max(1));
+
+    let (size_5star, size_points) = (max_5star as usize, max_points + 1);
+    let state_index = |since_5star: usize, guaranteed: bool, streak: usize, points: usize, other: bool| {
+        (((since_5star * 2 + guaranteed as usize) * streaks + streak) * size_points + points) * 2 + other as usize
+    };
+    let p_5star: Vec<f64> = (1..=max_5star).map(|count| rules.star5.pity.probability(count).clamp(0., 1.)).collect();
+    let p_target = 1. / featured.count as f64;
+
+    let states = size_5star * 2 * streaks * size_points * 2;
+    let mut current = vec![0.; states];
+    current[state_index(
+        (state.since_last_5star as usize).min(size_5star - 1),
+        !state.last_5star_is_up,
+        (state.lost_5star_streak as usize).min(streaks - 1),
+        points as usize,
+        false,
+    )] = 1.;
+
+    let mut exact = Exact5Star {
+        pmf_by_streak: vec![vec![0.]; streaks],
+        other_featured: 0.,
+        expected_other_featured: 0.,
+        expected_off_banner: 0.,
+    };
+    let mut remaining = 1.;
+    while remaining > EXACT_TOLERANCE {
+        if exact.pmf_by_streak[0].len() > MAX_EXACT_PULLS {
+            return None;
+        }
+        let mut next = vec![0.; states];
+        let mut done = vec![0.; streaks];
+        for since_5star in 0..size_5star {
+            for guaranteed in [false, true] {
+                for streak in 0..streaks {
+                    for points in 0..size_points {
+                        for other in [false, true] {
+                            let mass = current[state_index(since_5star, guaranteed, streak, points, other)];
+                            if mass == 0. {
+                                continue;
+                            }
+                            let chance_5star = p_5star[since_5star];
+                            next[state_index((since_5star + 1).min(size_5star - 1), guaranteed, streak, points, other)] += mass * (1. - chance_5star);
+                            let mass_5star = mass * chance_5star;
+                            if path.is_some() && points >= max_points {
+                                done[streak] += mass_5star;
+                                if other {
+                                    exact.other_featured += mass_5star;
+                                }
+                                continue;
+                            }
+
+                            // A roll is only made without the guarantee, leaving the streak as is otherwise
+                            let (up, won_streak) = if guaranteed { (1., streak) } else { (featured.up_probability(streak as u32), 0) };
+                            let missed_points = if path.is_some() { points + 1 } else { 0 };
+                            let target_chance = if path.is_some_and(|path| path.course) { 1. } else { p_target };
+                            let won = mass_5star * up;
+                            done[won_streak] += won * target_chance;
+                            if other {
+                                exact.other_featured += won * target_chance;
+                            }
+                            exact.expected_other_featured += won * (1. - target_chance);
+                            next[state_index(0, false, won_streak, missed_points, true)] += won * (1. - target_chance);
+
+                            let lost = mass_5star * (1. - up);
+                            exact.expected_off_banner += lost;
+                            next[state_index(0, featured.guarantee, (streak + 1).min(streaks - 1), missed_points, other)] += lost;
+                        }
+                    }
+                }
+            }
+        }
+        for (pmf, done) in exact.pmf_by_streak.iter_mut().zip(done) {
+            pmf.push(done);
+        }
+        remaining = next.iter().sum();
+        current = next;
+    }
+    Some(exact)
+}
+
+/// Distribution of the pulls needed for all copies of a featured 5 star
+pub struct ExactCopies {
+    pub pmf: Vec<f64>,
+    pub cdf: Vec<f64>,
+}
+
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut result = vec![0.; a.len() + b.len() - 1];
+    for (i, p) in a.iter().enumerate().filter(|(_, p)| **p > 0.) {
+        for (j, q) in b.iter().enumerate() {
+            result[i + j] += p * q;
+        }
+    }
+    result
+}
+
+fn add_into(total: &mut Vec<f64>, pmf: &[f64]) {
+    if total.len() < pmf.len() {
+        total.resize(pmf.len(), 0.);
+    }
+    for (total, p) in total.iter_mut().zip(pmf) {
+        *total += p;
+    }
+}
+
+/// Exact distribution of the pulls needed for `copies` of the featured 5 star `target`, by
+/// convolving the distribution of each copy. Every copy after the first starts from pity 0
+/// without the guarantee or fate points, only the Capturing Radiance streak and the course
+/// charted, if any, carrying over.
+pub fn featured_5star_copies(banner: &GachaBanner, target: u32, copies: u32) -> Result<ExactCopies, String> {
+    let max_copies = match banner.rules.item_type {
+        ItemType::Character => MAX_CHARACTER_COPIES,
+        ItemType::Weapon => MAX_WEAPON_COPIES,
+    };
+    if copies == 0 || copies > max_copies {
+        return Err(format!("Exact analysis covers 1 to {} copies on the {} banner", max_copies, banner.rules.name));
+    }
+    let unavailable = || format!("Exact analysis is not available for the {} banner with these rules and state", banner.rules.name);
+    let first = featured_5star_pmf(banner, target).ok_or_else(unavailable)?;
+    let streaks = first.pmf_by_streak.len();
+    // Distribution of each later copy by the streak it starts from
+    let mut later = Vec::with_capacity(streaks);
+    for streak in 0..streaks {
+        let mut next = banner.clone();
+        next.state.since_last_5star = 0;
+        next.state.last_5star_is_up = true;
+        next.state.lost_5star_streak = streak as u32;
+        next.state.want_5star_state = match next.state.want_5star_state {
+            WantState::Want(index, _) => WantState::Want(index, 0),
+            WantState::None => WantState::None,
+        };
+        later.push(featured_5star_pmf(&next, target).ok_or_else(unavailable)?);
+    }
+
+    let mut by_streak = first.pmf_by_streak;
+    for _ in 1..copies {
+        let mut next_by_streak = vec![Vec::new(); streaks];
+        for (streak, pmf) in by_streak.iter().enumerate().filter(|(_, pmf)| pmf.iter().any(|&p| p > 0.)) {
+            for (left, copy) in later[streak].pmf_by_streak.iter().enumerate() {
+                add_into(&mut next_by_streak[left], &convolve(pmf, copy));
+            }
+        }
+        by_streak = next_by_streak.into_iter()
+            .map(|pmf| if pmf.is_empty() { vec![0.] } else { pmf })
+            .collect();
+    }
+    let mut pmf = Vec::new();
+    for by_streak in &by_streak {
+        add_into(&mut pmf, by_streak);
+    }
+    let cdf = pmf.iter()
+        .scan(0., |done, p| {
+            *done += p;
+            Some(*done)
+        })
+        .collect();
+    Ok(ExactCopies { pmf, cdf })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::rng;
+
+    fn total_pmf(exact: &Exact5Star) -> Vec<f64> {
+        let mut pmf = Vec::new();
+        for by_streak in &exact.pmf_by_streak {
+            add_into(&mut pmf, by_streak);
+        }
+        pmf
+    }
+
+    #[test]
+    fn character_pmf_matches_known_values() {
+        let banner = GachaBanner::new(BannerRules::character());
+        let pmf = total_pmf(&featured_5star_pmf(&banner, 0).expect("No exact distribution for the character banner"));
+        let mean: f64 = pmf.iter().enumerate().map(|(pulls, p)| pulls as f64 * p).sum();
+        // Half the targets take one 5 star of mean 62.2973 pulls, the other half two
+        assert!((mean - 93.4459980594464).abs() < 1e-9, "mean {}", mean);
+        let within = |pulls: usize| pmf[..=pulls].iter().sum::<f64>();
+        assert!((within(90) - 0.5939383494228886).abs() < 1e-12, "{} within 90 pulls", within(90));
+        assert!((within(150) - 0.8001923569404706).abs() < 1e-12, "{} within 150 pulls", within(150));
+        // Two hard pities guarantee it, the chain being cut off once what is left is negligible
+        assert!(pmf.len() <= 181, "{} pulls", pmf.len() - 1);
+        assert!(1. - within(pmf.len() - 1) <= EXACT_TOLERANCE);
+    }
+
+    #[test]
+    fn copies_match_simulated_pulls() {
+        for path in [Some(0), None] {
+            let mut banner = GachaBanner::new(BannerRules::weapon());
+            banner.chart_path(path);
+            let exact = featured_5star_copies(&banner, 0, 2).expect("No exact distribution for the weapon banner");
+            let exact_mean: f64 = exact.pmf.iter().enumerate().map(|(pulls, p)| pulls as f64 * p).sum();
+            rng::seed(42);
+            let samples: Vec<f64> = (0..10_000)
+                .map(|_| pull_featured(&mut banner.clone(), None, 0, 2, u32::MAX).pulls as f64)
+                .collect();
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let variance = samples.iter().map(|pulls| (pulls - mean).powi(2)).sum::<f64>() / (samples.len() - 1) as f64;
+            let error = (variance / samples.len() as f64).sqrt();
+            assert!((mean - exact_mean).abs() <= 4. * error, "path {:?}: simulated {} against exact {}", path, mean, exact_mean);
+        }
+    }
+
+    #[test]
+    fn targets_never_won_have_no_distribution() {
+        let mut banner = GachaBanner::new(BannerRules::character());
+        if let Some(featured) = banner.rules.star5.featured.as_mut() {
+            featured.probability = 0.;
+            featured.guarantee = false;
+        }
+        assert!(featured_5star_pmf(&banner, 0).is_none());
+    }
+}
@@ -0,0 +1,243 @@
+pub mod exact;
+pub mod star4;
+pub mod worst_case;
+
+use super::common::{AnalyzeArgs, BannerArg, PlanArgs, RatesArgs, WorstCaseArgs};
+use super::gacha_impl::*;
+use super::gacha_impl::fast::FastSampler;
+use super::gacha_traits::{Banner, BannerRules, SparkPolicy};
+use super::gacha_traits::gacha_enums::*;
+
+use serde::de::DeserializeOwned;
+use std::fs;
+
+const QUANTILES: [f64; 7] = [0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 1.];
+/// Largest pity, in pulls, the exact calculations keep a state for
+const MAX_EXACT_PITY: u32 = 10_000;
+/// Most states the exact calculations keep a probability for
+const MAX_EXACT_STATES: usize = 1 << 20;
+/// Pulls after which an exact distribution still not settled is given up on
+const MAX_EXACT_PULLS: usize = 100_000;
+/// Probability left undecided at which the exact distributions are cut off
+const EXACT_TOLERANCE: f64 = 1e-12;
+
+pub fn load_state<T: DeserializeOwned>(file_path: &str) -> T {
+    let state = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Unable to read file: {}", file_path));
+    serde_json::from_str(&state)
+        .unwrap_or_else(|e| panic!("Unable to parse state in {}: {}", file_path, e))
+}
+
+/// Outcome of pulling for copies of a featured 5 star
+struct Pulled {
+    pulls: u32,
+    obtained: u32,
+    /// Copies exchanged for spark points, counted in `obtained` when of the target
+    exchanges: u32,
+}
+
+/// Pulls until the featured 5 star `target` is obtained `copies` times, or the budget
+/// runs out. Automatic spark exchanges are made as they come, manual ones only while
+/// copies are still missing. With a sampler, pulls jump from one 5 star to the next.
+fn pull_featured(banner: &mut GachaBanner, sampler: Option<&FastSampler>, target: u32, copies: u32, budget: u32) -> Pulled {
+    let mut pulled = Pulled { pulls: 0, obtained: 0, exchanges: 0 };
+    while pulled.obtained < copies && pulled.pulls < budget {
+        let (pulls, result) = match sampler {
+            Some(sampler) => {
+                // Stop where the spark is reached, for it to be exchanged on time
+                let until_spark = banner.rules.spark.as_ref()
+                    .map_or(u32::MAX, |spark| spark.threshold.saturating_sub(banner.state.spark_points).max(1));
+                banner.pull_to_next_5star(sampler, (budget - pulled.pulls).min(until_spark))
+            }
+            None => (1, Some(banner.pull())),
+        };
+        pulled.pulls += pulls;
+        if let Some(GachaResult::Up5Star(index)) = result {
+            if index == target {
+                pulled.obtained += 1;
+            }
+        }
+        let exchanged = match banner.rules.spark.as_ref().map(|spark| spark.policy) {
+            Some(SparkPolicy::Automatic) => banner.auto_exchange(),
+            Some(SparkPolicy::Manual) if pulled.obtained < copies => banner.exchange(target),
+            _ => None,
+        };
+        if let Some(result) = exchanged {
+            pulled.exchanges += 1;
+            if matches!(result, GachaResult::Up5Star(index) if index == target) {
+                pulled.obtained += 1;
+            }
+        }
+    }
+    pulled
+}
+
+/// Prints the mean and quantiles of sorted samples, and the share within the budget if any
+fn print_distribution(samples: &[u32], budget: Option<u32>) {
+    let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64;
+    println!("Mean: {:.2}", mean);
+    for quantile in QUANTILES {
+        let index = ((samples.len() as f64 * quantile).ceil() as usize).clamp(1, samples.len()) - 1;
+        println!("{:>5}%: {}", quantile * 100., samples[index]);
+    }
+    if let Some(budget) = budget {
+        let within = samples.partition_point(|&pulls| pulls <= budget);
+        println!("Within {} pulls: {}%", budget, within as f64 * 100. / samples.len() as f64);
+    }
+}
+
+/// Prints the mean and quantiles of an exact distribution, given as the chance of being done within
+/// each number of pulls, and the chance of being done within the budget if any
+fn print_exact_distribution(cdf: &[f64], budget: Option<u32>) {
+    let mean: f64 = cdf.iter().map(|done| 1. - done).sum();
+    println!("Mean: {:.2}", mean);
+    // The chain ran until all but a negligible mass was done, so its last pull is no maximum
+    for quantile in QUANTILES.into_iter().filter(|&quantile| quantile < 1.) {
+        let pulls = cdf.iter().position(|&done| done >= quantile).unwrap_or(cdf.len() - 1);
+        println!("{:>5}%: {}", quantile * 100., pulls);
+    }
+    let left = (1. - cdf.last().copied().unwrap_or(0.)).max(0.);
+    println!("Cut off after {} pulls, {:.1e} left", cdf.len() - 1, left);
+    if let Some(budget) = budget {
+        let done = cdf.get(budget as usize).or(cdf.last()).copied().unwrap_or(0.);
+        println!("Within {} pulls: {:.4}%", budget, done * 100.);
+    }
+}
+
+/// Banner to pull for the featured 5 star `target` on, from the state in `state` if given, the
+/// target being charted on the weapon banner unless `no_path`
+fn target_banner(pools: &Pools, banner: BannerArg, target: u32, no_path: bool, state: Option<&str>) -> (GachaBanner, u32) {
+    match banner {
+        BannerArg::Character => {
+            let mut initial = GachaBanner::new(pools.character_rules());
+            if let Some(file_path) = state {
+                initial.state = load_state(file_path);
+            }
+            (initial, 0)
+        }
+        BannerArg::Weapon => {
+            let mut initial = GachaBanner::new(pools.weapon_rules());
+            let featured = initial.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+            if target >= featured {
+                eprintln!("Target must be less than {}, the number of featured 5 star weapons", featured);
+                std::process::exit(1);
+            }
+            if let Some(file_path) = state {
+                initial.state = load_state(file_path);
+            }
+            if no_path {
+                initial.chart_path(None);
+            } else if !matches!(initial.state.want_5star_state, WantState::Want(index, _) if index == target) {
+                initial.chart_path(Some(target));
+            }
+            (initial, target)
+        }
+    }
+}
+
+pub fn analyze(pools: &Pools, args: &AnalyzeArgs) {
+    if let Some(item) = &args.item {
+        return star4::analyze_item(pools, args, item);
+    }
+    let (initial, target) = target_banner(pools, args.banner, args.target, args.no_path, args.state.as_deref());
+    if args.exact {
+        return analyze_exact(&initial, target, args);
+    }
+    let sampler = args.fast.then(|| FastSampler::new(&initial.rules));
+    let outcomes: Vec<Pulled> = (0..args.num_sim)
+        .map(|_| pull_featured(&mut initial.clone(), sampler.as_ref(), target, args.copies, u32::MAX))
+        .collect();
+    let mut samples: Vec<u32> = outcomes.iter().map(|outcome| outcome.pulls).collect();
+    samples.sort_unstable();
+
+    println!("Pulls needed for {} featured 5 star copies ({} simulations):", args.copies, args.num_sim);
+    print_distribution(&samples, args.budget);
+    if let Some(spark) = &initial.rules.spark {
+        let sparked = outcomes.iter().filter(|outcome| outcome.exchanges > 0).count();
+        println!("Spark (every {} pulls) exchanged in: {}%", spark.threshold, sparked as f64 * 100. / args.num_sim as f64);
+    }
+}
+
+/// Exact counterpart of `analyze`, printing the full distribution as CSV if asked
+fn analyze_exact(initial: &GachaBanner, target: u32, args: &AnalyzeArgs) {
+    let copies = match exact::featured_5star_copies(initial, target, args.copies) {
+        Ok(copies) => copies,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    if args.full {
+        println!("pulls,pmf,cdf");
+        for (pulls, (pmf, cdf)) in copies.pmf.iter().zip(&copies.cdf).enumerate() {
+            println!("{},{},{}", pulls, pmf, cdf);
+        }
+        return;
+    }
+    println!("Exact pulls needed for {} copies of featured 5 star #{}:", args.copies, target);
+    print_exact_distribution(&copies.cdf, args.budget);
+    if args.copies == 1 {
+        if let Some(exact) = exact::featured_5star_pmf(initial, target) {
+            println!("Other featured 5 star obtained first: {:.4}% ({:.4} expected)", exact.other_featured * 100., exact.expected_other_featured);
+            println!("Off-banner 5 stars obtained first: {:.4} expected", exact.expected_off_banner);
+        }
+    }
+}
+
+pub fn plan(pools: &Pools, args: &PlanArgs) {
+    let mut character_initial = GachaBanner::new(pools.character_rules());
+    if let Some(file_path) = &args.character_state {
+        character_initial.state = load_state(file_path);
+    }
+    let mut weapon_initial = GachaBanner::new(pools.weapon_rules());
+    if let Some(file_path) = &args.weapon_state {
+        weapon_initial.state = load_state(file_path);
+    }
+    let target = match weapon_initial.state.want_5star_state {
+        WantState::Want(index, _) => index,
+        WantState::None => 0,
+    };
+
+    let character_sampler = args.fast.then(|| FastSampler::new(&character_initial.rules));
+    let weapon_sampler = args.fast.then(|| FastSampler::new(&weapon_initial.rules));
+
+    let mut character_success = 0u32;
+    let mut success = 0u32;
+    let mut characters_total = 0u64;
+    let mut weapons_total = 0u64;
+    let mut remaining_total = 0u64;
+    let mut exchanges_total = 0u64;
+    for _ in 0..args.num_sim {
+        let mut character_banner = character_initial.clone();
+        let mut weapon_banner = weapon_initial.clone();
+        if let WantState::None = weapon_banner.state.want_5star_state {
+            weapon_banner.chart_path(Some(target));
+        }
+
+        let characters = pull_featured(&mut character_banner, character_sampler.as_ref(), 0, args.characters, args.pulls);
+        let weapons = pull_featured(&mut weapon_banner, weapon_sampler.as_ref(), target, args.weapons, args.pulls - characters.pulls);
+        characters_total += characters.obtained as u64;
+        weapons_total += weapons.obtained as u64;
+        exchanges_total += (characters.exchanges + weapons.exchanges) as u64;
+        if characters.obtained == args.characters {
+            character_success += 1;
+            if weapons.obtained == args.weapons {
+                success += 1;
+                remaining_total += (args.pulls - characters.pulls - weapons.pulls) as u64;
+            }
+        }
+    }
+
+    let percent = |count: u32| count as f64 * 100. / args.num_sim as f64;
+    println!("Plan: {} featured characters then {} charted weapons with {} pulls ({} simulations)", args.characters, args.weapons, args.pulls, args.num_sim);
+    println!("Character goal probability: {}%", percent(character_success));
+    println!("Whole plan probability: {}%", percent(success));
+    println!("Expected featured characters: {:.3}", characters_total as f64 / args.num_sim as f64);
+    println!("Expected charted weapons: {:.3}", weapons_total as f64 / args.num_sim as f64);
+    if character_initial.rules.spark.is_some() || weapon_initial.rules.spark.is_some() {
+        println!("Expected spark exchanges: {:.3}", exchanges_total as f64 / args.num_sim as f64);
+    }
+    if success > 0 {
+        println!("Average pulls left on success: {:.2}", remaining_total as f64 / success as f64);
+    }
+}
@@ -0,0 +1,357 @@
+use super::*;
+
+/// Exact distribution of the pulls needed for `copies` of one featured 4 star, all being
+/// equally likely, as the probability of having them all within each number of pulls, by a Markov
+/// chain over the 5 and 4 star pity counters and the 4 star guarantee. `None` on
+/// banners it cannot model: scripted pulls, Capturing Radiance on 4 stars or pity
+/// too long to keep the states for.
+pub fn featured_4star_cdf(banner: &GachaBanner, copies: u32, max_pulls: u32) -> Option<Vec<f64>> {
+    if copies == 0 {
+        return Some(vec![1.]);
+    }
+    let rules = &banner.rules;
+    let featured = rules.star4.featured.as_ref()?;
+    let (max_5star, max_4star) = (rules.star5.pity.max, rules.star4.pity.max);
+    if !rules.script.is_empty() || featured.radiance.is_some() || max_5star > MAX_EXACT_PITY || max_4star > MAX_EXACT_PITY {
+        return None;
+    }
+    let (size_5star, size_4star) = (max_5star as usize, max_4star as usize + 1);
+    let state_index = |since_5star: usize, since_4star: usize, guaranteed: bool| {
+        (since_5star * size_4star + since_4star) * 2 + guaranteed as usize
+    };
+    let p_5star: Vec<f64> = (1..=max_5star).map(|count| rules.star5.pity.probability(count).clamp(0., 1.)).collect();
+    let p_4star: Vec<f64> = (1..=max_4star + 1).map(|count| rules.star4.pity.probability(count).clamp(0., 1.)).collect();
+    let p_target = 1. / featured.count as f64;
+
+    // Probability of each state with each number of copies short of the goal
+    let states = size_5star * size_4star * 2;
+    if states * copies as usize > MAX_EXACT_STATES {
+        return None;
+    }
+    let mut current = vec![vec![0.; states]; copies as usize];
+    let state = &banner.state;
+    current[0][state_index(
+        (state.since_last_5star as usize).min(size_5star - 1),
+        (state.since_last_4star as usize).min(size_4star - 1),
+        !state.last_4star_is_up,
+    )] = 1.;
+
+    let mut cdf = vec![0.];
+    let mut done = 0.;
+    // Rounding leaks a little mass, so the chain stops on what is left in it rather than on `done`
+    let mut remaining = 1.;
+    while cdf.len() <= max_pulls as usize && remaining > EXACT_TOLERANCE {
+        let mut next = vec![vec![0.; states]; copies as usize];
+        for (obtained, distribution) in current.iter().enumerate() {
+            for since_5star in 0..size_5star {
+                for since_4star in 0..size_4star {
+                    for guaranteed in [false, true] {
+                        let mass = distribution[state_index(since_5star, since_4star, guaranteed)];
+                        if mass == 0. {
+                            continue;
+                        }
+                        let next_5star = (since_5star + 1).min(size_5star - 1);
+                        let next_4star = (since_4star + 1).min(size_4star - 1);
+                        let chance_5star = p_5star[since_5star];
+                        let chance_4star = (chance_5star + p_4star[since_4star]).min(1.) - chance_5star;
+                        let chance_3star = 1. - chance_5star - chance_4star;
+                        next[obtained][state_index(0, next_4star, guaranteed)] += mass * chance_5star;
+                        next[obtained][state_index(next_5star, next_4star, guaranteed)] += mass * chance_3star;
+
+                        // A 4 star: featured when guaranteed or won, then the target with even odds
+                        let up = if guaranteed { 1. } else { featured.probability };
+                        let target = mass * chance_4star * up * p_target;
+                        if obtained + 1 == copies as usize {
+                            done += target;
+                        } else {
+                            next[obtained + 1][state_index(next_5star, 0, false)] += target;
+                        }
+                        next[obtained][state_index(next_5star, 0, false)] += mass * chance_4star * up * (1. - p_target);
+                        next[obtained][state_index(next_5star, 0, featured.guarantee)] += mass * chance_4star * (1. - up);
+                    }
+                }
+            }
+        }
+        remaining = next.iter().flatten().sum();
+        current = next;
+        cdf.push(done);
+    }
+    Some(cdf)
+}
+
+/// Whether pulls on a banner with `rules` can ever give the 4 star `name` found as `item`: featured,
+/// or in an off-banner list of a type the banner gives with a weight left as of the version simulated
+fn obtainable(rules: &BannerRules, pool: &BannerPool, name: &str, item: &PoolItem) -> bool {
+    if item.up_index.is_some() {
+        return rules.star4.featured.is_some();
+    }
+    let gives = |item_type| rules.star4.balance.is_some() || rules.item_type == item_type;
+    pool.off_banner_lists().iter()
+        .filter(|(field, _)| match *field {
+            "other_4star_character" => gives(ItemType::Character),
+            "other_4star_weapon" => gives(ItemType::Weapon),
+            _ => false,
+        })
+        .any(|(_, items)| items.iter().enumerate().any(|(index, item)| item == name && items.weight(index) > 0.))
+}
+
+/// Pulls until `copies` of the 4 star `name` are obtained, whether featured or picked off-banner
+fn pull_item(banner: &mut GachaBanner, pool: &BannerPool, name: &str, copies: u32) -> u32 {
+    let mut pulls = 0;
+    let mut obtained = 0;
+    while obtained < copies {
+        pulls += 1;
+        let result = banner.pull();
+        if result.get_item_level() == ItemLevel::Star4 && result.pick_item_name(pool) == name {
+            obtained += 1;
+        }
+    }
+    pulls
+}
+
+/// Pulls needed for copies of a 4 star, such as 7 for a C6 character, simulated
+/// and, for featured 4 stars, calculated exactly
+pub fn analyze_item(pools: &Pools, args: &AnalyzeArgs, name: &str) {
+    let (mut banner, pool, item_type) = match args.banner {
+        BannerArg::Character => (GachaBanner::new(pools.character_rules()), &pools.character1, ItemType::Character),
+        BannerArg::Weapon => (GachaBanner::new(pools.weapon_rules()), &pools.weapon, ItemType::Weapon),
+    };
+    let item = match pool.find_item(name, item_type) {
+        Some(item) if item.level == ItemLevel::Star4 => item,
+        _ => {
+            eprintln!("{} is not a 4 star of the {:?} banner pool", name, args.banner);
+            std::process::exit(1);
+        }
+    };
+    if !obtainable(&banner.rules, pool, name, &item) {
+        eprintln!("{} cannot be obtained on the {:?} banner as of {}", name, args.banner, pools.as_of.as_deref().unwrap_or("now"));
+        std::process::exit(1);
+    }
+    if let Some(file_path) = &args.state {
+        banner.state = load_state(file_path);
+    }
+
+    let mut samples: Vec<u32> = (0..args.num_sim)
+        .map(|_| pull_item(&mut banner.clone(), pool, name, args.copies))
+        .collect();
+    samples.sort_unstable();
+    println!("Pulls needed for {} copies of {} ({} simulations):", args.copies, name, args.num_sim);
+    print_distribution(&samples, args.budget);
+
+    // The chain only follows featured drops, which an item also picked off-banner is not limited to
+    let off_banner = pool.off_banner_lists().iter().any(|(_, items)| items.contains(&name.to_string()));
+    let exact = match item.up_index {
+        Some(_) if !off_banner => featured_4star_cdf(&banner, args.copies, u32::MAX),
+        _ => None,
+    };
+    let Some(cdf) = exact else {
+        println!("Exact: not available for this item and banner");
+        return;
+    };
+    println!("Exact:");
+    print_exact_distribution(&cdf, args.budget);
+}
+
+/// Long-run share of pulls giving each kind of 4 star
+pub struct Stationary4Star {
+    pub featured: f64,
+    pub off_banner_character: f64,
+    pub off_banner_weapon: f64,
+}
+
+impl Stationary4Star {
+    pub fn total(&self) -> f64 {
+        self.featured + self.off_banner_character + self.off_banner_weapon
+    }
+}
+
+/// Iterations after which the stationary distribution is given up on
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Exact long-run 4 star rates of a banner, as the stationary distribution of a Markov chain
+/// over the 5 and 4 star pity counters, the 4 star guarantee and the balance counters. The
+/// balance counter of the type of the last 4 star always equals the 4 star pity counter, so
+/// the chain only keeps the other one, up to where its type becomes certain. `None` on banners
+/// it cannot model: Capturing Radiance on 4 stars, pity too long to keep the states for or a
+/// balance whose type never becomes certain.
+pub fn stationary_4star(rules: &BannerRules) -> Option<Stationary4Star> {
+    let featured = rules.star4.featured.as_ref();
+    let (max_5star, max_4star) = (rules.star5.pity.max, rules.star4.pity.max);
+    if featured.is_some_and(|featured| featured.radiance.is_some()) || max_5star > MAX_EXACT_PITY || max_4star > MAX_EXACT_PITY {
+        return None;
+    }
+    let base = rules.star4.pity.base_probability;
+    let balance = rules.star4.balance.as_ref();
+    // Counter of the other type past which its weight reaches 1 and it is always picked
+    let cap = match balance {
+        Some(balance) => (1..=MAX_EXACT_PITY)
+            .find(|&count| balance.probability(base, count) >= 1.)?
+            .saturating_sub(1)
+            .max(max_4star + 1) as usize,
+        None => 0,
+    };
+    let (size_5star, size_4star, size_other) = (max_5star as usize, max_4star as usize + 1, cap + 1);
+    let states = size_5star * size_4star * 2 * 2 * size_other;
+    if states > MAX_EXACT_STATES {
+        return None;
+    }
+    let state_index = |since_5star: usize, since_4star: usize, guaranteed: bool, last: ItemType, other: usize| {
+        ((((since_5star * size_4star + since_4star) * 2 + guaranteed as usize) * 2 + (last == ItemType::Weapon) as usize) * size_other) + other
+    };
+    let p_5star: Vec<f64> = (1..=max_5star).map(|count| rules.star5.pity.probability(count).clamp(0., 1.)).collect();
+    let p_4star: Vec<f64> = (1..=max_4star + 1).map(|count| rules.star4.pity.probability(count).clamp(0., 1.)).collect();
+    let other_type = |item_type: ItemType| match item_type {
+        ItemType::Character => ItemType::Weapon,
+        ItemType::Weapon => ItemType::Character,
+    };
+
+    // The 5 star counter does not depend on the rest, so it starts from its own stationary
+    // distribution, proportional to the chance of no 5 star in as many pulls, and the chain
+    // only has the 4 star side left to settle
+    let survival: Vec<f64> = p_5star.iter()
+        .scan(1., |survival: &mut f64, chance| {
+            let current = *survival;
+            *survival *= 1. - chance;
+            Some(current)
+        })
+        .collect();
+    let total: f64 = survival.iter().sum();
+    let mut current = vec![0.; states];
+    for (since_5star, survival) in survival.iter().enumerate() {
+        current[state_index(since_5star, 0, false, rules.item_type, 0)] = survival / total;
+    }
+    for _ in 0..MAX_ITERATIONS {
+        let mut next = vec![0.; states];
+        let mut rates = Stationary4Star { featured: 0., off_banner_character: 0., off_banner_weapon: 0. };
+        for since_5star in 0..size_5star {
+            for since_4star in 0..size_4star {
+                for guaranteed in [false, true] {
+                    for last in [ItemType::Character, ItemType::Weapon] {
+                        for other in 0..size_other {
+                            let mass = current[state_index(since_5star, since_4star, guaranteed, last, other)];
+                            if mass == 0. {
+                                continue;
+                            }
+                            let next_5star = (since_5star + 1).min(size_5star - 1);
+                            let next_4star = (since_4star + 1).min(size_4star - 1);
+                            let next_other = (other + 1).min(cap);
+                            let chance_5star = p_5star[since_5star];
+                            let chance_4star = (chance_5star + p_4star[since_4star]).min(1.) - chance_5star;
+                            let chance_3star = 1. - chance_5star - chance_4star;
+                            next[state_index(0, next_4star, guaranteed, last, next_other)] += mass * chance_5star;
+                            next[state_index(next_5star, next_4star, guaranteed, last, next_other)] += mass * chance_3star;
+
+                            // A 4 star of a type resets its counter; the counter of the last type, equal
+                            // to the 4 star pity, becomes the other one if the type changes
+                            let mut land = |item_type: ItemType, guaranteed: bool, mass: f64| {
+                                let other = if item_type == last { next_other } else { (since_4star + 1).min(cap) };
+                                next[state_index(next_5star, 0, guaranteed, item_type, other)] += mass;
+                            };
+                            let up = match featured {
+                                Some(_) if guaranteed => 1.,
+                                Some(featured) => featured.probability,
+                                None => 0.,
+                            };
+                            let mass_4star = mass * chance_4star;
+                            rates.featured += mass_4star * up;
+                            land(rules.item_type, false, mass_4star * up);
+
+                            let lost = mass_4star * (1. - up);
+                            let guarantee = featured.is_some_and(|featured| featured.guarantee);
+                            let character = match balance {
+                                Some(balance) => {
+                                    let (count_last, count_other) = (since_4star as u32 + 1, other as u32 + 1);
+                                    match last {
+                                        ItemType::Character => balance.character_probability(base, count_last, count_other),
+                                        ItemType::Weapon => balance.character_probability(base, count_other, count_last),
+                                    }
+                                }
+                                None if rules.item_type == ItemType::Character => 1.,
+                                None => 0.,
+                            };
+                            rates.off_banner_character += lost * character;
+                            rates.off_banner_weapon += lost * (1. - character);
+                            land(ItemType::Character, guarantee, lost * character);
+                            land(other_type(ItemType::Character), guarantee, lost * (1. - character));
+                        }
+                    }
+                }
+            }
+        }
+        // Staying put half the time keeps the stationary distribution but stops the chain from
+        // cycling around a 4 star pity reached like clockwork
+        for (next, current) in next.iter_mut().zip(&current) {
+            *next = (*next + current) / 2.;
+        }
+        let change: f64 = current.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+        current = next;
+        if change < EXACT_TOLERANCE {
+            return Some(rates);
+        }
+    }
+    None
+}
+
+/// Prints the exact long-run 4 star rates of a banner
+pub fn rates(pools: &Pools, args: &RatesArgs) {
+    let rules = match args.banner {
+        BannerArg::Character => pools.character_rules(),
+        BannerArg::Weapon => pools.weapon_rules(),
+    };
+    let Some(rates) = stationary_4star(&rules) else {
+        println!("Exact 4 star rates are not available for the {} banner", rules.name);
+        return;
+    };
+    let total = rates.total();
+    println!("Exact long-run 4 star rates of the {} banner:", rules.name);
+    println!("4 star rate: {:.4}% (one every {:.2} pulls)", total * 100., 1. / total);
+    println!("Featured share: {:.4}%", rates.featured / total * 100.);
+    println!("Off-banner share: {:.4}%", (rates.off_banner_character + rates.off_banner_weapon) / total * 100.);
+    println!("Off-banner characters: {:.4}% of 4 stars", rates.off_banner_character / total * 100.);
+    println!("Off-banner weapons: {:.4}% of 4 stars", rates.off_banner_weapon / total * 100.);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::rng;
+
+    #[test]
+    fn stationary_rates_match_simulated_pulls() {
+        let rules = BannerRules::character();
+        let rates = stationary_4star(&rules).expect("No stationary rates for the character banner");
+        let mut banner = GachaBanner::new(rules);
+        rng::seed(42);
+        let pulls = 300_000;
+        let (mut featured, mut character, mut weapon) = (0u32, 0u32, 0u32);
+        for _ in 0..pulls {
+            match banner.pull() {
+                GachaResult::Up4Star(_) => featured += 1,
+                GachaResult::Other4Star(ItemType::Character) => character += 1,
+                GachaResult::Other4Star(ItemType::Weapon) => weapon += 1,
+                _ => {}
+            }
+        }
+        // Pity makes the drops more regular than independent pulls, so their standard error is an upper bound
+        for (count, rate) in [
+            (featured + character + weapon, rates.total()),
+            (featured, rates.featured),
+            (character, rates.off_banner_character),
+            (weapon, rates.off_banner_weapon),
+        ] {
+            let error = (rate * (1. - rate) / pulls as f64).sqrt();
+            let share = count as f64 / pulls as f64;
+            assert!((share - rate).abs() <= 4. * error, "simulated {} against exact {}", share, rate);
+        }
+    }
+
+    #[test]
+    fn balance_never_certain_has_no_stationary_rates() {
+        let mut rules = BannerRules::character();
+        rules.star4.pity.base_probability = 0.;
+        assert!(stationary_4star(&rules).is_none());
+        rules.star4.balance = None;
+        let rates = stationary_4star(&rules).expect("No stationary rates without balance");
+        assert!((rates.total() - 1. / rules.star4.pity.max as f64).abs() < 1e-3, "{}", rates.total());
+    }
+}
@@ -0,0 +1,246 @@
+use super::*;
+use crate::common::rng::{self, DrawSource};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// What the 5 star side of a banner depends on, the 4 star counters having no say in it
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    since_5star: u32,
+    last_5star_is_up: bool,
+    lost_5star_streak: u32,
+    want: Option<(u32, u32)>,
+    spark_points: u32,
+    /// Pulls made, only up to the last scripted one they decide
+    pulls: u32,
+    obtained: u32,
+}
+
+/// 5 stars needed for the target at worst from a guarantee, fate points and streak, `None` if
+/// nothing in the rules stops the featured roll from being lost or missing the target forever
+fn worst_5stars(rules: &BannerRules, target: u32, guaranteed: bool, want: WantState, streak: u32) -> Option<u32> {
+    let featured = rules.star5.featured.as_ref()?;
+    let mut candidates = Vec::new();
+    if let (Some(path), WantState::Want(index, points)) = (&rules.path, want) {
+        if index == target {
+            candidates.push(path.max_fate_points - points.min(path.max_fate_points) + 1);
+        }
+    }
+    // Winning the featured roll gives the target only when it is the one featured or charted as the course
+    if featured.count == 1 || rules.path.as_ref().is_some_and(|path| path.course) {
+        if guaranteed {
+            candidates.push(1);
+        }
+        if featured.guarantee {
+            candidates.push(2);
+        }
+        if let Some(radiance) = &featured.radiance {
+            let certain = (streak as usize..radiance.probabilities.len().max(streak as usize + 1))
+                .find(|&lost| featured.up_probability(lost as u32) >= 1.);
+            if let Some(lost) = certain {
+                candidates.push(lost as u32 - streak + 1);
+            }
+        }
+    }
+    candidates.into_iter().min()
+}
+
+/// Worst-case pulls for `copies` of the featured 5 star `target` derived from the rule parameters
+/// alone: hard pity times the 5 stars needed at worst for each copy, or the spark threshold per
+/// copy if it can be exchanged for the target, whichever is lower. `None` if neither bounds them.
+pub fn derived_bound(banner: &GachaBanner, target: u32, copies: u32) -> Option<u32> {
+    let rules = &banner.rules;
+    let state = &banner.state;
+    let max = rules.star5.pity.max;
+    let reset_want = match state.want_5star_state {
+        WantState::Want(index, _) => WantState::Want(index, 0),
+        WantState::None => WantState::None,
+    };
+    let pity = worst_5stars(rules, target, !state.last_5star_is_up, state.want_5star_state, state.lost_5star_streak)
+        .zip(worst_5stars(rules, target, false, reset_want, 0))
+        .map(|(first, later)| {
+            let first = max.saturating_sub(state.since_last_5star) + (first - 1) * max;
+            first + (copies - 1) * later * max
+        });
+    let spark = rules.spark.as_ref()
+        .filter(|spark| spark.policy == SparkPolicy::Manual || banner.spark_target() == target)
+        .map(|spark| (copies * spark.threshold).saturating_sub(state.spark_points));
+    match (pity, spark) {
+        (Some(pity), Some(spark)) => Some(pity.min(spark)),
+        (pity, spark) => pity.or(spark),
+    }
+}
+
+/// Draws of a pull chosen by the search, recording how many outcomes each one made had
+#[derive(Default)]
+struct Choices {
+    /// Outcome chosen for each draw, the first one for the draws past the end
+    chosen: Vec<u64>,
+    outcomes: Vec<u64>,
+}
+
+impl Choices {
+    fn next(&mut self, outcomes: u64) -> u64 {
+        let chosen = self.chosen.get(self.outcomes.len()).copied().unwrap_or(0);
+        self.outcomes.push(outcomes);
+        chosen
+    }
+}
+
+/// Makes the draws the engine asks for from the choices shared with the search. A `random`
+/// draw is at its lowest or highest, which settles every probability it is compared with
+/// either way, and a `gen_range` draw at any value of its range.
+struct Adversary(Rc<RefCell<Choices>>);
+
+impl DrawSource for Adversary {
+    fn word(&mut self) -> u64 {
+        if self.0.borrow_mut().next(2) == 0 { 0 } else { u64::MAX }
+    }
+
+    fn offset(&mut self, len: u64) -> u64 {
+        self.0.borrow_mut().next(len)
+    }
+}
+
+/// Every outcome of a pull on `banner` the adversary can choose, found by making the pull
+/// again with each outcome of the first draw not chosen yet
+fn pull_outcomes(banner: &GachaBanner) -> Vec<(GachaBanner, GachaResult)> {
+    let mut outcomes = Vec::new();
+    let mut pending = vec![Vec::new()];
+    while let Some(chosen) = pending.pop() {
+        let choices = Rc::new(RefCell::new(Choices { chosen, outcomes: Vec::new() }));
+        let mut next = banner.clone();
+        rng::draw_from(Box::new(Adversary(Rc::clone(&choices))));
+        let result = next.pull();
+        rng::draw_at_random();
+        let Choices { chosen, outcomes: made } = Rc::try_unwrap(choices).ok().expect("Adversary still drawing").into_inner();
+        match made.get(chosen.len()) {
+            Some(&count) => pending.extend((0..count).map(|outcome| [chosen.as_slice(), &[outcome]].concat())),
+            None => outcomes.push((next, result)),
+        }
+    }
+    outcomes
+}
+
+/// Depth-first search for the most pulls an adversary choosing every draw can force, memoized
+/// on what the 5 star side depends on. Reaching a state being searched again means the adversary
+/// can go round in circles, so the goal is never certain.
+struct Search {
+    target: u32,
+    copies: u32,
+    /// Pull after which scripted items no longer change anything
+    last_script: u32,
+    /// Lost streak after which the featured chance no longer changes
+    last_streak: u32,
+    memo: HashMap<Key, Option<u32>>,
+    searching: HashSet<Key>,
+}
+
+impl Search {
+    fn key(&self, banner: &GachaBanner, obtained: u32) -> Key {
+        let state = &banner.state;
+        Key {
+            since_5star: state.since_last_5star,
+            last_5star_is_up: state.last_5star_is_up,
+            lost_5star_streak: state.lost_5star_streak.min(self.last_streak),
+            want: match state.want_5star_state {
+                WantState::Want(index, points) => Some((index, points)),
+                WantState::None => None,
+            },
+            spark_points: state.spark_points,
+            pulls: state.pulls.min(self.last_script),
+            obtained,
+        }
+    }
+
+    fn worst(&mut self, banner: &GachaBanner, obtained: u32) -> Option<u32> {
+        if obtained >= self.copies {
+            return Some(0);
+        }
+        let key = self.key(banner, obtained);
+        if let Some(worst) = self.memo.get(&key) {
+            return *worst;
+        }
+        if !self.searching.insert(key) {
+            return None;
+        }
+        let mut worst = Some(0);
+        for (mut next, result) in pull_outcomes(banner) {
+            let mut got = obtained + matches!(result, GachaResult::Up5Star(index) if index == self.target) as u32;
+            // Spark exchanges as `analyze` makes them
+            let exchanged = match next.rules.spark.as_ref().map(|spark| spark.policy) {
+                Some(SparkPolicy::Automatic) => next.auto_exchange(),
+                Some(SparkPolicy::Manual) if got < self.copies => next.exchange(self.target),
+                _ => None,
+            };
+            if matches!(exchanged, Some(GachaResult::Up5Star(index)) if index == self.target) {
+                got += 1;
+            }
+            worst = worst.zip(self.worst(&next, got)).map(|(worst, rest)| worst.max(rest + 1));
+            if worst.is_none() {
+                break;
+            }
+        }
+        self.searching.remove(&key);
+        self.memo.insert(key, worst);
+        worst
+    }
+}
+
+/// Most pulls `copies` of the featured 5 star `target` can take on the rule engine, `None` if unbounded
+pub fn adversarial_worst(banner: &GachaBanner, target: u32, copies: u32) -> Option<u32> {
+    let mut search = Search {
+        target,
+        copies,
+        last_script: banner.rules.script.iter().map(|script| script.by).max().unwrap_or(0),
+        last_streak: banner.rules.star5.featured.as_ref()
+            .and_then(|featured| featured.radiance.as_ref())
+            .map_or(0, |radiance| radiance.probabilities.len().saturating_sub(1) as u32),
+        memo: HashMap::new(),
+        searching: HashSet::new(),
+    };
+    search.worst(banner, 0)
+}
+
+pub fn worst_case(pools: &Pools, args: &WorstCaseArgs) {
+    let (initial, target) = target_banner(pools, args.banner, args.target, args.no_path, args.state.as_deref());
+    let describe = |pulls: Option<u32>| pulls.map_or(String::from("unbounded"), |pulls| format!("{} pulls", pulls));
+    let derived = derived_bound(&initial, target, args.copies);
+    let copies = if args.copies == 1 { "copy" } else { "copies" };
+    println!("Worst case for {} {} of featured 5 star #{} on the {} banner:", args.copies, copies, target, initial.rules.name);
+    println!("Derived from the rules: {}", describe(derived));
+    let searched = adversarial_worst(&initial, target, args.copies);
+    println!("Adversarial search: {}", describe(searched));
+    match (derived, searched) {
+        (Some(derived), Some(searched)) if searched == derived => println!("The bound holds and is reached"),
+        (Some(derived), Some(searched)) if searched < derived => println!("The bound holds, the engine never needing more than {}", searched),
+        (None, None) => println!("No number of pulls guarantees the goal"),
+        (None, Some(_)) => println!("The rules alone give no bound, yet the engine never needs more"),
+        _ => {
+            eprintln!("The engine can need more pulls than the derived bound");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes_cover_every_featured_item() {
+        let mut banner = GachaBanner::new(BannerRules::chronicled());
+        banner.chart_path(None);
+        banner.state.since_last_5star = banner.rules.star5.pity.max - 1;
+        let mut featured: Vec<u32> = pull_outcomes(&banner).into_iter()
+            .filter_map(|(_, result)| match result {
+                GachaResult::Up5Star(index) => Some(index),
+                _ => None,
+            })
+            .collect();
+        featured.sort_unstable();
+        featured.dedup();
+        assert_eq!(featured, [0, 1, 2, 3]);
+    }
+}
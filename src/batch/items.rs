@@ -0,0 +1,256 @@
+use super::*;
+use crate::gacha_impl::item_list::ItemList;
+use std::borrow::Cow;
+
+/// Pool lists broken down per item, in the order they are reported
+const LISTS: [&str; 7] = [
+    "up_5star",
+    "up_5star_weapon",
+    "up_4star",
+    "other_5star",
+    "other_5star_weapon",
+    "other_4star_character",
+    "other_4star_weapon",
+];
+
+/// Pearson's chi-square test of the drops of a list against a pick by the configured weights,
+/// uniform unless the pool configuration gives some
+#[derive(Serialize)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: u32,
+    /// Chance of a statistic at least this large if the picks follow the weights
+    pub p_value: f64,
+}
+
+/// Drops of every item of a pool list on a banner, most frequent first
+#[derive(Serialize)]
+pub struct ListBreakdown {
+    pub banner: String,
+    pub list: &'static str,
+    pub items: Vec<(String, u32)>,
+    /// Fit of the picks to the weights, for off-banner lists of at least two items that dropped
+    pub chi_square: Option<ChiSquare>,
+}
+
+/// Natural logarithm of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146, -86.50532032941677, 24.01409824083091,
+        -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS.iter().enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1. + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Regularized upper incomplete gamma function Q(a, x), by its series below
+/// `a + 1` and its continued fraction above
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 1.;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1. {
+        let mut term = 1. / a;
+        let mut sum = term;
+        for n in 1..1000 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        1. - sum * prefactor
+    } else {
+        let tiny = 1e-300;
+        let mut b = x + 1. - a;
+        let mut c = 1. / tiny;
+        let mut d = 1. / b;
+        let mut h = d;
+        for n in 1..1000 {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1. / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.).abs() < 1e-15 {
+                break;
+            }
+        }
+        prefactor * h
+    }
+}
+
+/// Items that cannot be picked, having no weight, are left out of the test
+fn chi_square(counts: &[u32], items: &ItemList) -> Option<ChiSquare> {
+    let total: u32 = counts.iter().sum();
+    let weights: Vec<f64> = (0..counts.len()).map(|index| items.weight(index)).collect();
+    let total_weight: f64 = weights.iter().sum();
+    let pickable = weights.iter().filter(|&&weight| weight > 0.).count();
+    if pickable < 2 || total == 0 {
+        return None;
+    }
+    let statistic = counts.iter().zip(&weights)
+        .filter(|(_, &weight)| weight > 0.)
+        .map(|(&count, weight)| {
+            let expected = total as f64 * weight / total_weight;
+            (count as f64 - expected).powi(2) / expected
+        })
+        .sum();
+    let degrees_of_freedom = pickable as u32 - 1;
+    Some(ChiSquare {
+        statistic,
+        degrees_of_freedom,
+        p_value: gamma_q(degrees_of_freedom as f64 / 2., statistic / 2.),
+    })
+}
+
+/// Pulls `num_sim` times on a banner, resolving every drop to an item of `pool`
+pub fn item_breakdown(name: &str, banner: &mut dyn Banner, pool: &BannerPool, num_sim: u32) -> Vec<ListBreakdown> {
+    let mut counts: Vec<(&'static str, Vec<u32>)> = Vec::new();
+    let mut names: Vec<&[String]> = Vec::new();
+    let mut off_banner: Vec<Option<&ItemList>> = Vec::new();
+    for _ in 0..num_sim {
+        let result = banner.pull();
+        let (list, items) = result.pool_list(pool);
+        let index = result.pick_item_index(pool);
+        let position = match counts.iter().position(|(l, _)| *l == list) {
+            Some(position) => position,
+            None => {
+                counts.push((list, vec![0; items.len()]));
+                names.push(items);
+                off_banner.push(result.off_banner_list(pool).map(|(_, items)| items));
+                counts.len() - 1
+            }
+        };
+        counts[position].1[index] += 1;
+    }
+
+    LISTS.iter()
+        .filter_map(|list| {
+            let position = counts.iter().position(|(l, _)| l == list)?;
+            let list_counts = &counts[position].1;
+            let mut items: Vec<(String, u32)> = names[position].iter().cloned().zip(list_counts.iter().copied()).collect();
+            items.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            Some(ListBreakdown {
+                banner: name.to_string(),
+                list,
+                items,
+                chi_square: off_banner[position].and_then(|items| chi_square(list_counts, items)),
+            })
+        })
+        .collect()
+}
+
+/// Breakdown of the character, weapon and Chronicled Wish banners, each pulled `num_sim` times
+pub fn simulate_items(num_sim: u32, pools: &Pools) -> Vec<ListBreakdown> {
+    let mut banners = vec![
+        ("character", pools.character_rules(), &pools.character1),
+        ("weapon", pools.weapon_rules(), &pools.weapon),
+    ];
+    if let (Some(rules), Some(pool)) = (pools.chronicled_rules(), &pools.chronicled) {
+        banners.push(("chronicled", rules, pool));
+    }
+    banners.into_iter()
+        .flat_map(|(name, rules, pool)| item_breakdown(name, &mut GachaBanner::new(rules), pool, num_sim))
+        .collect()
+}
+
+/// Pads `text` to `width` terminal columns, CJK characters taking two
+fn pad(text: &str, width: usize) -> String {
+    let columns: usize = text.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(columns)))
+}
+
+/// Prints a breakdown as a table, with the drops of each item per `per_pulls` pulls,
+/// that is the expected constellations for featured 4 stars
+pub fn print_table(breakdowns: &[ListBreakdown], num_sim: u32, per_pulls: u32) {
+    for breakdown in breakdowns {
+        println!();
+        println!("{} {} ({} pulls)", breakdown.banner, breakdown.list, num_sim);
+        println!("  {:<24} {:>10} {:>14}", "Item", "Drops", format!("Per {} pulls", per_pulls));
+        for (item, count) in &breakdown.items {
+            println!("  {} {:>10} {:>14.4}", pad(item, 24), count, *count as f64 * per_pulls as f64 / num_sim as f64);
+        }
+        if let Some(chi_square) = &breakdown.chi_square {
+            println!("  Chi-square {:.3} ({} degrees of freedom), weighted pick p-value {:.4}", chi_square.statistic, chi_square.degrees_of_freedom, chi_square.p_value);
+        }
+    }
+}
+
+/// Same as `print_table` as CSV, the chi-square test repeated on every item of its list
+/// Quotes a CSV field holding a separator, quote or line break, doubling its quotes
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+pub fn print_csv(breakdowns: &[ListBreakdown], num_sim: u32, per_pulls: u32) {
+    println!("banner,list,item,drops,per_{}_pulls,chi_square,degrees_of_freedom,p_value", per_pulls);
+    for breakdown in breakdowns {
+        let chi_square = match &breakdown.chi_square {
+            Some(chi_square) => format!("{},{},{}", chi_square.statistic, chi_square.degrees_of_freedom, chi_square.p_value),
+            None => String::from(",,"),
+        };
+        for (item, count) in &breakdown.items {
+            println!(
+                "{},{},{},{},{},{}",
+                csv_field(&breakdown.banner), csv_field(breakdown.list), csv_field(item),
+                count, *count as f64 * per_pulls as f64 / num_sim as f64, chi_square,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ln_gamma_matches_known_values() {
+        let cases = [
+            (1., 0.),
+            (2., 0.),
+            (0.5, std::f64::consts::PI.sqrt().ln()),
+            (10., 362880f64.ln()),
+            (100.5, 361.4355404677776),
+        ];
+        for (x, expected) in cases {
+            assert!((ln_gamma(x) - expected).abs() < 1e-9, "ln_gamma({}) = {}, expected {}", x, ln_gamma(x), expected);
+        }
+    }
+
+    #[test]
+    fn gamma_q_matches_known_values() {
+        // Q(1, x) is e^-x, and Q(k / 2, x / 2) the p-value of a chi-square of x with k degrees of freedom
+        let cases = [
+            (1., 0.5, (-0.5f64).exp()),
+            (1., 5., (-5f64).exp()),
+            (0.5, 3.841458820694124 / 2., 0.05),
+            (5., 18.307038053275146 / 2., 0.05),
+            (5., 23.209251158954356 / 2., 0.01),
+            (50., 50., 0.481191684527957),
+            (3., 0., 1.),
+        ];
+        for (a, x, expected) in cases {
+            assert!((gamma_q(a, x) - expected).abs() < 1e-9, "gamma_q({}, {}) = {}, expected {}", a, x, gamma_q(a, x), expected);
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("阿莫斯之弓"), "阿莫斯之弓");
+        assert_eq!(csv_field("Sword, Long"), "\"Sword, Long\"");
+        assert_eq!(csv_field("The \"Catch\""), "\"The \"\"Catch\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,138 @@
+pub mod items;
+
+use super::gacha_impl::*;
+use super::gacha_traits::*;
+use super::gacha_traits::gacha_enums::*;
+
+use serde::Serialize;
+
+/// Aggregate drop rates of a batch simulation, in percent
+#[derive(Serialize)]
+pub struct BatchStatistics {
+    pub num_sim: u32,
+    pub character_up_5star: f64,
+    pub character_5star: f64,
+    pub weapon_want_5star: f64,
+    pub weapon_up_5star: f64,
+    pub weapon_5star: f64,
+    /// Chronicled Wish rates, when the rule set and pool configuration have one
+    pub chronicled_charted_5star: Option<f64>,
+    pub chronicled_5star: Option<f64>,
+    pub standard_5star: f64,
+    /// Spark exchanges on the character and weapon banners, when they have a spark
+    pub character_spark: Option<SparkStatistics>,
+    pub weapon_spark: Option<SparkStatistics>,
+    /// Chance of a 5 star before the Beginners' Wish runs out, with the pulls
+    /// and fates that takes, when the rule set and pool configuration have one
+    pub beginner: Option<LimitedStatistics>,
+}
+
+#[derive(Serialize)]
+pub struct SparkStatistics {
+    pub threshold: u32,
+    pub exchanges: u32,
+    /// Featured 5 star probability per pull, counting exchanged copies
+    pub up_5star_with_spark: f64,
+}
+
+/// 5 star drops of a banner, with featured drops counted per featured item
+struct Counts5Star {
+    up: Vec<u32>,
+    total: u32,
+    /// Featured 5 stars obtained from the spark rather than pulled
+    exchanges: u32,
+}
+
+/// Pulls `num_sim` times, moving on to the next banner every `banner_pulls` pulls.
+/// Automatic exchanges are made as soon as possible, manual ones before each banner ends.
+fn count_5star(banner: &mut dyn Banner, num_sim: u32, banner_pulls: Option<u32>) -> Counts5Star {
+    let featured = banner.rules().star5.featured.as_ref().map_or(0, |featured| featured.count);
+    let mut counts = Counts5Star {
+        up: vec![0; featured as usize],
+        total: 0,
+        exchanges: 0,
+    };
+    for pull in 1..=num_sim {
+        match banner.pull() {
+            GachaResult::Up5Star(index) => {
+                counts.up[index as usize] += 1;
+                counts.total += 1;
+            }
+            GachaResult::Other5Star(_) => {
+                counts.total += 1;
+            }
+            _ => {}
+        }
+        counts.exchanges += banner.auto_exchange().is_some() as u32;
+        let banner_ends = pull == num_sim || banner_pulls.is_some_and(|banner_pulls| pull % banner_pulls == 0);
+        if banner_ends {
+            let target = banner.spark_target();
+            while banner.exchange(target).is_some() {
+                counts.exchanges += 1;
+            }
+            banner.next_banner();
+        }
+    }
+    counts
+}
+
+#[derive(Serialize)]
+pub struct LimitedStatistics {
+    pub pulls: u32,
+    pub fates: u32,
+    pub probability_5star: f64,
+}
+
+/// Number of fresh banners out of `runs` yielding a 5 star before their limit runs out
+fn count_5star_within_limit(rules: &BannerRules, runs: u32) -> u32 {
+    (0..runs)
+        .filter(|_| GachaBanner::new(rules.clone()).pull_n(u32::MAX).iter()
+            .any(|result| result.get_item_level() == ItemLevel::Star5))
+        .count() as u32
+}
+
+/// Simulates every banner, the featured lineups changing every `banner_pulls` pulls if set
+pub fn simulate_batch(num_sim: u32, banner_pulls: Option<u32>, pools: &Pools) -> BatchStatistics {
+    let mut banners: Vec<Box<dyn Banner>> = vec![
+        Box::new(GachaBanner::new(pools.character_rules())),
+        Box::new(GachaBanner::new(pools.weapon_rules())),
+        Box::new(GachaBanner::new(pools.standard_rules())),
+    ];
+    if let Some(rules) = pools.chronicled_rules() {
+        banners.push(Box::new(GachaBanner::new(rules)));
+    }
+    let counts: Vec<Counts5Star> = banners.iter_mut()
+        .map(|banner| count_5star(banner.as_mut(), num_sim, banner_pulls))
+        .collect();
+    let spark = |banner: &dyn Banner, counts: &Counts5Star| banner.rules().spark.as_ref().map(|spark| SparkStatistics {
+        threshold: spark.threshold,
+        exchanges: counts.exchanges,
+        up_5star_with_spark: (counts.up.iter().sum::<u32>() + counts.exchanges) as f64 * 100. / num_sim as f64,
+    });
+
+    let beginner = pools.beginner_rules().map(|rules| {
+        let pulls = rules.limit.unwrap_or(1).max(1);
+        let runs = (num_sim / pulls).max(1);
+        LimitedStatistics {
+            pulls,
+            fates: rules.cost(pulls),
+            probability_5star: count_5star_within_limit(&rules, runs) as f64 * 100. / runs as f64,
+        }
+    });
+
+    let percent = |count: u32| count as f64 * 100. / num_sim as f64;
+    BatchStatistics {
+        num_sim,
+        character_up_5star: percent(counts[0].up.iter().sum()),
+        character_5star: percent(counts[0].total),
+        weapon_want_5star: percent(counts[1].up[0]),
+        weapon_up_5star: percent(counts[1].up.iter().sum()),
+        weapon_5star: percent(counts[1].total),
+        chronicled_charted_5star: counts.get(3).map(|counts| percent(counts.up[0])),
+        chronicled_5star: counts.get(3).map(|counts| percent(counts.total)),
+        standard_5star: percent(counts[2].total),
+        character_spark: spark(banners[0].as_ref(), &counts[0]),
+        weapon_spark: spark(banners[1].as_ref(), &counts[1]),
+        beginner,
+    }
+}
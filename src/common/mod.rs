@@ -1,18 +1,244 @@
-use clap::Parser;
+pub mod rng;
+
+use crate::gacha_traits::rule_sets::RULE_SETS;
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 /// Genshin Impact Gacha Simulator @LI Runzhong
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Arguments {
+    /// File path of the gacha pool configuration file
+    #[arg(short, long, global = true, default_value_t = String::from("pool.json"))]
+    pub file_path: String,
+
+    /// Seed for the random number generator, for reproducible results
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Rule set to simulate under, overriding the one named in the pool configuration file
+    #[arg(long, global = true, value_parser = PossibleValuesParser::new(RULE_SETS))]
+    pub rules: Option<String>,
+
+    /// Version or date to simulate as of, overriding the one in the pool configuration
+    /// file and leaving out the off-banner items only available after it
+    #[arg(long, global = true)]
+    pub as_of: Option<String>,
+
+    /// Trace how every pull was decided to stderr, as text or, with --explain=json, as JSON lines
+    #[arg(long, global = true, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    pub explain: Option<ExplainFormat>,
+
+    /// Mode to run in, simulate when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Simulate pulls on every banner and report aggregate drop rates
+    Simulate(SimulateArgs),
+    /// Pull interactively, in a full-screen terminal UI when available
+    Interactive(InteractiveArgs),
+    /// Distribution of the number of pulls needed for featured 5 stars
+    Analyze(AnalyzeArgs),
+    /// Chance of reaching character and weapon goals within a pull budget
+    Plan(PlanArgs),
+    /// Exact long-run 4 star rates of a banner, featured and off-banner by type
+    Rates(RatesArgs),
+    /// Most pulls featured 5 star copies can take, derived from the rules and checked against the engine
+    WorstCase(WorstCaseArgs),
+    /// Reconstruct the pity state of a banner from a pull log
+    Import(ImportArgs),
+    /// Check the gacha pool configuration file
+    ValidatePool,
+    /// Serve the simulator as a local HTTP/JSON API
+    Serve(ServeArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BannerArg {
+    Character,
+    Weapon,
+}
+
+#[derive(Args, Debug)]
+pub struct SimulateArgs {
     /// Number of times to simulate gacha
-    #[arg(short, long, default_value_t = 10000)]
+    #[arg(short, long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
     pub num_sim: u32,
 
-    /// File path of the gacha pool configuration file
-    #[arg(short, long, default_value_t = String::from("pool.json"))]
-    pub file_path: String,
+    /// Pulls made on each banner before the next one, for sparks that do not carry over
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub banner_pulls: Option<u32>,
+
+    /// Break the drops down per item of the pool configuration
+    #[arg(long, default_value_t = false)]
+    pub items: bool,
+
+    /// Output format of the per-item breakdown
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Number of pulls the per-item drop rates are given for
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub per_pulls: u32,
+}
+
+impl Default for SimulateArgs {
+    fn default() -> SimulateArgs {
+        SimulateArgs { num_sim: 10000, banner_pulls: None, items: false, format: OutputFormat::Table, per_pulls: 100 }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExplainFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct InteractiveArgs {
+    /// Use the line-based interactive mode even when running in a terminal
+    #[arg(long, default_value_t = false)]
+    pub plain: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Banner to analyze
+    #[arg(short, long, value_enum, default_value_t = BannerArg::Character)]
+    pub banner: BannerArg,
+
+    /// Number of featured 5 star copies to obtain, or of the 4 star item if given
+    #[arg(short, long, default_value_t = 1)]
+    pub copies: u32,
+
+    /// Index of the featured weapon to obtain on the weapon banner
+    #[arg(short, long, default_value_t = 0)]
+    pub target: u32,
+
+    /// Do not chart the target weapon on the Epitomized Path
+    #[arg(long, default_value_t = false)]
+    pub no_path: bool,
+
+    /// 4 star to obtain copies of instead, such as 7 for a C6 character, featured or not
+    #[arg(short, long)]
+    pub item: Option<String>,
+
+    /// Pull budget to report the chance of reaching the goal within
+    #[arg(long)]
+    pub budget: Option<u32>,
+
+    /// Pity state to start from, as written by the import command
+    #[arg(short, long)]
+    pub state: Option<String>,
+
+    /// Sample from one 5 star to the next instead of pulling one by one, for large runs
+    #[arg(long, default_value_t = false)]
+    pub fast: bool,
+
+    /// Compute the distribution exactly from the rules instead of simulating
+    #[arg(long, default_value_t = false, conflicts_with = "fast")]
+    pub exact: bool,
+
+    /// With --exact, print the probability of being done on and by each pull as CSV
+    #[arg(long, default_value_t = false, requires = "exact")]
+    pub full: bool,
+
+    /// Number of times to simulate gacha
+    #[arg(short, long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub num_sim: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// Number of pulls available
+    #[arg(short, long)]
+    pub pulls: u32,
+
+    /// Copies of the featured 5 star character wanted, pulled first
+    #[arg(short, long, default_value_t = 1)]
+    pub characters: u32,
+
+    /// Copies of the charted 5 star weapon wanted, pulled with the remaining budget
+    #[arg(short, long, default_value_t = 0)]
+    pub weapons: u32,
+
+    /// Character banner pity state to start from, as written by the import command
+    #[arg(long)]
+    pub character_state: Option<String>,
+
+    /// Weapon banner pity state to start from, as written by the import command
+    #[arg(long)]
+    pub weapon_state: Option<String>,
+
+    /// Sample from one 5 star to the next instead of pulling one by one, for large runs
+    #[arg(long, default_value_t = false)]
+    pub fast: bool,
+
+    /// Number of times to simulate gacha
+    #[arg(short, long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub num_sim: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct RatesArgs {
+    /// Banner to compute the rates of
+    #[arg(short, long, value_enum, default_value_t = BannerArg::Character)]
+    pub banner: BannerArg,
+}
+
+#[derive(Args, Debug)]
+pub struct WorstCaseArgs {
+    /// Banner to pull on
+    #[arg(short, long, value_enum, default_value_t = BannerArg::Character)]
+    pub banner: BannerArg,
+
+    /// Number of featured 5 star copies to obtain
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub copies: u32,
+
+    /// Index of the featured weapon to obtain on the weapon banner
+    #[arg(short, long, default_value_t = 0)]
+    pub target: u32,
+
+    /// Do not chart the target weapon on the Epitomized Path
+    #[arg(long, default_value_t = false)]
+    pub no_path: bool,
+
+    /// Pity state to start from, as written by the import command
+    #[arg(short, long)]
+    pub state: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Banner the pull log was recorded on
+    #[arg(short, long, value_enum)]
+    pub banner: BannerArg,
+
+    /// Pull log, a JSON array of pulls from oldest to newest with their item names
+    pub log_path: String,
+
+    /// Index of the featured weapon charted on the Epitomized Path during the log
+    #[arg(short, long)]
+    pub path: Option<u32>,
+
+    /// Write the reconstructed state to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
 
-    /// Interactive mode
-    #[arg(short, long, default_value_t = false)]
-    pub interactive: bool,
-}
\ No newline at end of file
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(short, long, default_value_t = String::from("127.0.0.1:8080"))]
+    pub address: String,
+}
@@ -0,0 +1,60 @@
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::mock::StepRng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::ops::Range;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+    static SOURCE: RefCell<Option<Box<dyn DrawSource>>> = const { RefCell::new(None) };
+}
+
+/// Where draws come from instead of the random number generator, such as a search
+/// trying each outcome of a pull
+pub trait DrawSource {
+    /// Word a `random` draw is made from, as if the generator gave only this one
+    fn word(&mut self) -> u64;
+    /// Offset from its start of the value a `gen_range` draw gives in a range of `len` values
+    fn offset(&mut self, len: u64) -> u64;
+}
+
+/// Reseeds the random number generator of the current thread, making every
+/// following simulation on it reproducible
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Makes the following draws on the current thread come from `source`, until `draw_at_random`
+pub fn draw_from(source: Box<dyn DrawSource>) {
+    SOURCE.with(|s| *s.borrow_mut() = Some(source));
+}
+
+/// Goes back to drawing from the random number generator
+pub fn draw_at_random() {
+    SOURCE.with(|s| *s.borrow_mut() = None);
+}
+
+pub fn random<T>() -> T where Standard: Distribution<T> {
+    if let Some(word) = SOURCE.with(|s| s.borrow_mut().as_mut().map(|source| source.word())) {
+        return StepRng::new(word, 0).gen();
+    }
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+pub fn gen_range<T>(range: Range<T>) -> T
+where
+    T: SampleUniform + PartialOrd + Copy + TryInto<u64> + TryFrom<u64>,
+{
+    let drawn = SOURCE.with(|s| {
+        let mut source = s.borrow_mut();
+        let source = source.as_mut()?;
+        let (start, end) = (range.start.try_into().ok()?, range.end.try_into().ok()?);
+        T::try_from(start + source.offset(end - start)).ok()
+    });
+    match drawn {
+        Some(value) => value,
+        None => RNG.with(|rng| rng.borrow_mut().gen_range(range)),
+    }
+}
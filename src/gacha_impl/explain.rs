@@ -0,0 +1,176 @@
+use super::*;
+use crate::common::ExplainFormat;
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static FORMAT: Cell<Option<ExplainFormat>> = const { Cell::new(None) };
+    static TRACE: RefCell<Option<PullTrace>> = const { RefCell::new(None) };
+}
+
+/// Featured roll of a tier, or the guarantee that skipped it
+#[derive(Serialize)]
+pub struct FeaturedTrace {
+    pub level: ItemLevel,
+    /// Whether the last featured roll of the tier was lost, guaranteeing this one
+    pub guaranteed: bool,
+    pub lost_streak: u32,
+    pub up_probability: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f64>,
+    pub won: bool,
+}
+
+/// Epitomized Path consulted for a 5 star
+#[derive(Serialize)]
+pub struct PathTrace {
+    pub charted: u32,
+    pub fate_points: u32,
+    pub max_fate_points: u32,
+    /// Whether the fate points alone gave the charted item, without a featured roll
+    pub fated: bool,
+}
+
+/// Choice between a character and a weapon for an off-banner item
+#[derive(Serialize)]
+pub struct BalanceTrace {
+    pub level: ItemLevel,
+    pub count_character: u32,
+    pub count_weapon: u32,
+    pub p_character: f64,
+    pub p_weapon: f64,
+    /// Absent when both are equally likely and a coin was flipped instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f64>,
+    pub item_type: ItemType,
+}
+
+/// How one pull was decided, from the pity counters before it to its result
+#[derive(Serialize)]
+pub struct PullTrace {
+    pub banner: String,
+    /// Number of the pull on the banner, from 1
+    pub pull: u32,
+    pub before: GachaState,
+    pub prob_5star: f64,
+    pub prob_4star: f64,
+    pub roll: f64,
+    /// Level a scripted item forced regardless of the roll
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forced: Option<ItemLevel>,
+    pub level: ItemLevel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scripted: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathTrace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub featured: Option<FeaturedTrace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<BalanceTrace>,
+    pub result: String,
+}
+
+/// Traces every following pull on the current thread to stderr
+pub fn enable(format: ExplainFormat) {
+    FORMAT.with(|f| f.set(Some(format)));
+}
+
+pub fn enabled() -> bool {
+    FORMAT.with(|f| f.get().is_some())
+}
+
+/// Starts the trace of a pull about to be made on `banner`
+pub fn begin(banner: &GachaBanner) {
+    if !enabled() {
+        return;
+    }
+    let trace = PullTrace {
+        banner: banner.rules.name.clone(),
+        pull: banner.state.pulls + 1,
+        before: banner.state.clone(),
+        prob_5star: 0.,
+        prob_4star: 0.,
+        roll: 0.,
+        forced: None,
+        level: ItemLevel::Star3,
+        scripted: None,
+        path: None,
+        featured: None,
+        balance: None,
+        result: String::new(),
+    };
+    TRACE.with(|t| *t.borrow_mut() = Some(trace));
+}
+
+/// Adds to the trace of the pull being made, if any; pulls sampled by `--fast` have none
+pub fn record(update: impl FnOnce(&mut PullTrace)) {
+    TRACE.with(|t| {
+        if let Some(trace) = t.borrow_mut().as_mut() {
+            update(trace);
+        }
+    });
+}
+
+fn describe(result: &GachaResult) -> String {
+    let item_type = |item_type: &ItemType| match item_type {
+        ItemType::Character => "character",
+        ItemType::Weapon => "weapon",
+    };
+    match result {
+        GachaResult::Up5Star(index) => format!("featured 5 star #{}", index),
+        GachaResult::Up4Star(index) => format!("featured 4 star #{}", index),
+        GachaResult::Other5Star(t) => format!("off-banner 5 star {}", item_type(t)),
+        GachaResult::Other4Star(t) => format!("off-banner 4 star {}", item_type(t)),
+        GachaResult::Other3Star => String::from("3 star"),
+    }
+}
+
+fn level_name(level: ItemLevel) -> &'static str {
+    match level {
+        ItemLevel::Star5 => "5 star",
+        ItemLevel::Star4 => "4 star",
+        ItemLevel::Star3 => "3 star",
+    }
+}
+
+fn print_text(trace: &PullTrace) {
+    let before = &trace.before;
+    eprintln!("[{} #{}] since 5 star {}, since 4 star {}", trace.banner, trace.pull, before.since_last_5star, before.since_last_4star);
+    let forced = trace.forced.map_or(String::new(), |level| format!(", forced to {} by script", level_name(level)));
+    eprintln!("  prob_5star {:.4}, prob_4star {:.4}, roll {:.6} -> {}{}", trace.prob_5star, trace.prob_4star, trace.roll, level_name(trace.level), forced);
+    if let Some(index) = trace.scripted {
+        eprintln!("  scripted featured #{}", index);
+    }
+    if let Some(path) = &trace.path {
+        eprintln!("  path: charted #{}, fate points {}/{}{}", path.charted, path.fate_points, path.max_fate_points, if path.fated { ", fated" } else { "" });
+    }
+    if let Some(featured) = &trace.featured {
+        let roll = featured.roll.map_or(String::from("no roll"), |roll| format!("roll {:.6}", roll));
+        eprintln!(
+            "  {} featured: guaranteed {}, lost streak {}, up_probability {:.4}, {} -> {}",
+            level_name(featured.level), featured.guaranteed, featured.lost_streak, featured.up_probability, roll,
+            if featured.won { "won" } else { "lost" },
+        );
+    }
+    if let Some(balance) = &trace.balance {
+        let roll = balance.roll.map_or(String::from("coin flip"), |roll| format!("roll {:.6}", roll));
+        eprintln!(
+            "  {} balance: character {:.4} (count {}), weapon {:.4} (count {}), {} -> {}",
+            level_name(balance.level), balance.p_character, balance.count_character, balance.p_weapon, balance.count_weapon, roll,
+            match balance.item_type { ItemType::Character => "character", ItemType::Weapon => "weapon" },
+        );
+    }
+    eprintln!("  result: {}", trace.result);
+}
+
+/// Ends the trace of a pull with its result and writes it out
+pub fn finish(result: &GachaResult) {
+    let Some(mut trace) = TRACE.with(|t| t.borrow_mut().take()) else {
+        return;
+    };
+    trace.result = describe(result);
+    match FORMAT.with(|f| f.get()) {
+        Some(ExplainFormat::Text) => print_text(&trace),
+        Some(ExplainFormat::Json) => eprintln!("{}", serde_json::to_string(&trace).expect("Unable to serialize pull trace")),
+        None => {}
+    }
+}
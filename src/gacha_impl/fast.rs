@@ -0,0 +1,230 @@
+use super::*;
+
+/// Survival entries kept in the table, beyond which the pity curve is walked pull by pull
+const MAX_TABLE: usize = 1 << 16;
+
+/// Inverse CDF of the pulls until the next 5 star of a banner, for jumping straight
+/// to it instead of rolling every pull in between
+pub struct FastSampler {
+    pity_5star: PityRules,
+    pity_4star: PityRules,
+    /// Probability of each tier by pulls since the last one, from the first pull,
+    /// up to the pull that guarantees it
+    probabilities_5star: Vec<f64>,
+    probabilities_4star: Vec<f64>,
+    /// Chance of no 5 star within the first `i` pulls after the last one, for each `i`
+    survival: Vec<f64>,
+}
+
+/// Probability of a tier as the per-pull roll applies it, capped at 1
+fn probability(pity: &PityRules, count: u32) -> f64 {
+    pity.probability(count).clamp(0., 1.)
+}
+
+fn probability_table(pity: &PityRules) -> Vec<f64> {
+    let mut probabilities = Vec::new();
+    while probabilities.last() != Some(&1.) && probabilities.len() < MAX_TABLE {
+        probabilities.push(probability(pity, probabilities.len() as u32 + 1));
+    }
+    probabilities
+}
+
+fn lookup(probabilities: &[f64], pity: &PityRules, count: u32) -> f64 {
+    match probabilities.get(count as usize - 1) {
+        Some(probability) => *probability,
+        None if probabilities.last() == Some(&1.) => 1.,
+        None => probability(pity, count),
+    }
+}
+
+impl FastSampler {
+    pub fn new(rules: &BannerRules) -> FastSampler {
+        let probabilities_5star = probability_table(&rules.star5.pity);
+        let mut survival = vec![1.];
+        for probability in &probabilities_5star {
+            survival.push(survival[survival.len() - 1] * (1. - probability));
+        }
+        FastSampler {
+            pity_5star: rules.star5.pity.clone(),
+            pity_4star: rules.star4.pity.clone(),
+            probabilities_4star: probability_table(&rules.star4.pity),
+            probabilities_5star,
+            survival,
+        }
+    }
+
+    /// Samples the pulls until the next 5 star given the pulls since the last one,
+    /// or `None` if it comes after `budget` pulls
+    fn gap_5star(&self, since_last_5star: u32, budget: u32) -> Option<u32> {
+        let threshold = 1. - rng::random::<f64>();
+        let start = since_last_5star as usize;
+        let mut relative = 1.;
+        if let Some(&from) = self.survival.get(start) {
+            if from == 0. {
+                return (budget >= 1).then_some(1);
+            }
+            let rest = &self.survival[start + 1..];
+            let offset = rest.partition_point(|&survival| survival > threshold * from);
+            if offset < rest.len() {
+                let gap = offset as u32 + 1;
+                return (gap <= budget).then_some(gap);
+            }
+            relative = self.survival[self.survival.len() - 1] / from;
+        }
+        // Past the table, only reached by pity curves that take very long to guarantee a 5 star
+        let mut count = (start + 1).max(self.survival.len()) as u32;
+        loop {
+            let gap = count - since_last_5star;
+            if gap > budget {
+                return None;
+            }
+            relative *= 1. - probability(&self.pity_5star, count);
+            if relative <= threshold {
+                return Some(gap);
+            }
+            count += 1;
+        }
+    }
+
+    /// Samples which of the next `pulls` pulls, known to give no 5 star, gives the
+    /// next 4 star, as an offset from 1
+    fn gap_4star(&self, state: &GachaState, pulls: u32) -> Option<u32> {
+        let threshold = 1. - rng::random::<f64>();
+        let mut survival = 1.;
+        for gap in 1..=pulls {
+            let p_5star = lookup(&self.probabilities_5star, &self.pity_5star, state.since_last_5star + gap);
+            let p_4star = lookup(&self.probabilities_4star, &self.pity_4star, state.since_last_4star + gap);
+            // A roll missing the 5 star is uniform over the rest of the range
+            let hazard = if p_5star < 1. { p_4star.min(1. - p_5star) / (1. - p_5star) } else { 1. };
+            survival *= 1. - hazard;
+            if survival <= threshold {
+                return Some(gap);
+            }
+        }
+        None
+    }
+}
+
+impl GachaBanner {
+    /// Counts `pulls` 3 star pulls at once
+    fn skip_3star(&mut self, pulls: u32) {
+        let state = &mut self.state;
+        state.pulls += pulls;
+        state.since_last_5star += pulls;
+        state.since_last_4star += pulls;
+        state.since_last_5star_character += pulls;
+        state.since_last_5star_weapon += pulls;
+        state.since_last_4star_character += pulls;
+        state.since_last_4star_weapon += pulls;
+        if self.rules.spark.is_some() {
+            state.spark_points += pulls;
+        }
+    }
+
+    /// Pulls up to `budget` times, stopping at the first 5 star, which is returned with
+    /// the number of pulls made. The pulls in between are sampled a notable drop at a
+    /// time from `sampler`, built from the rules of this banner, leaving the banner in
+    /// the same state as pulling one by one would in distribution.
+    pub fn pull_to_next_5star(&mut self, sampler: &FastSampler, budget: u32) -> (u32, Option<GachaResult>) {
+        let mut pulls = 0;
+        // Scripted pulls force their level, which the pity alone does not tell
+        while self.pending_scripts().next().is_some() {
+            if pulls == budget {
+                return (pulls, None);
+            }
+            pulls += 1;
+            let result = self.pull_one();
+            if result.get_item_level() == ItemLevel::Star5 {
+                return (pulls, Some(result));
+            }
+        }
+
+        let gap = sampler.gap_5star(self.state.since_last_5star, budget - pulls);
+        let mut before = gap.map_or(budget - pulls, |gap| gap - 1);
+        while let Some(offset) = sampler.gap_4star(&self.state, before) {
+            self.skip_3star(offset - 1);
+            self.get_item_level(Some(ItemLevel::Star4), false);
+            self.resolve(ItemLevel::Star4, None);
+            before -= offset;
+        }
+        self.skip_3star(before);
+        match gap {
+            Some(gap) => {
+                self.get_item_level(Some(ItemLevel::Star5), false);
+                (pulls + gap, Some(self.resolve(ItemLevel::Star5, None)))
+            }
+            None => (budget, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIVE_STARS: usize = 20_000;
+
+    /// Pulls between consecutive 5 stars, the share of them featured and the 4 star pity left
+    /// after each, from pulling one by one or with `sampler`
+    fn sample(rules: &BannerRules, sampler: Option<&FastSampler>) -> (Vec<f64>, f64, Vec<f64>) {
+        rng::seed(42);
+        let mut banner = GachaBanner::new(rules.clone());
+        let (mut gaps, mut featured, mut since_4star) = (Vec::with_capacity(FIVE_STARS), 0, Vec::with_capacity(FIVE_STARS));
+        while gaps.len() < FIVE_STARS {
+            let (gap, result) = match sampler {
+                Some(sampler) => {
+                    let (gap, result) = banner.pull_to_next_5star(sampler, u32::MAX);
+                    (gap, result.expect("No 5 star within the budget"))
+                }
+                None => {
+                    let mut gap = 0;
+                    loop {
+                        gap += 1;
+                        let result = banner.pull();
+                        if result.get_item_level() == ItemLevel::Star5 {
+                            break (gap, result);
+                        }
+                    }
+                }
+            };
+            gaps.push(gap as f64);
+            featured += result.is_up() as u32;
+            since_4star.push(banner.state.since_last_4star as f64);
+        }
+        gaps.sort_unstable_by(f64::total_cmp);
+        (gaps, featured as f64 / FIVE_STARS as f64, since_4star)
+    }
+
+    /// Whether two samples have the same mean, within 4 standard errors of their difference
+    fn same_mean(a: &[f64], b: &[f64]) -> bool {
+        let moments = |sample: &[f64]| {
+            let mean = sample.iter().sum::<f64>() / sample.len() as f64;
+            let variance = sample.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (sample.len() - 1) as f64;
+            (mean, variance / sample.len() as f64)
+        };
+        let ((mean_a, error_a), (mean_b, error_b)) = (moments(a), moments(b));
+        (mean_a - mean_b).abs() < 4. * (error_a + error_b).sqrt()
+    }
+
+    fn cross_check(rules: BannerRules) {
+        let (slow, slow_featured, slow_4star) = sample(&rules, None);
+        let (fast, fast_featured, fast_4star) = sample(&rules, Some(&FastSampler::new(&rules)));
+        assert!(same_mean(&slow, &fast), "{}: pulls between 5 stars differ in mean", rules.name);
+        for quantile in [0.1, 0.25, 0.5, 0.75, 0.9, 0.99] {
+            let at = (quantile * FIVE_STARS as f64) as usize;
+            assert!((slow[at] - fast[at]).abs() <= 2., "{}: {} quantile {} against {}", rules.name, quantile, fast[at], slow[at]);
+        }
+        assert!((slow_featured - fast_featured).abs() < 0.02, "{}: featured {} against {}", rules.name, fast_featured, slow_featured);
+        assert!(same_mean(&slow_4star, &fast_4star), "{}: 4 star pity after a 5 star differs in mean", rules.name);
+    }
+
+    #[test]
+    fn fast_matches_pulls_on_weapon() {
+        cross_check(BannerRules::weapon());
+    }
+
+    #[test]
+    fn fast_matches_pulls_on_arknights() {
+        cross_check(RuleSet::named("arknights").unwrap().character);
+    }
+}
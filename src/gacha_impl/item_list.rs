@@ -0,0 +1,178 @@
+use crate::common::rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::ops::Deref;
+
+/// An entry of a pool list, either a bare name or a name with a weight and
+/// the version or date the item is available from
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Name(String),
+    Item {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weight: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        available_from: Option<String>,
+    },
+}
+
+/// Off-banner items of a tier, picked uniformly unless some carry a weight
+/// or are not available yet as of the version or date simulated
+#[derive(Clone, Default)]
+pub struct ItemList {
+    names: Vec<String>,
+    entries: Vec<Entry>,
+    /// Weight each item is picked with, 0 if it is not available yet
+    weights: Vec<f64>,
+    /// Whether every item is picked with the same weight, with the same draws as before weights existed
+    uniform: bool,
+}
+
+/// Compares versions or dates such as "4.0" and "2023-10-18" number by number,
+/// trailing zeros making no difference
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |version: &str| -> Vec<u64> {
+        let mut numbers: Vec<u64> = version.split(|c: char| !c.is_ascii_digit())
+            .filter(|number| !number.is_empty())
+            .map(|number| number.parse().unwrap_or(u64::MAX))
+            .collect();
+        while numbers.last() == Some(&0) {
+            numbers.pop();
+        }
+        numbers
+    };
+    numbers(a).cmp(&numbers(b))
+}
+
+impl ItemList {
+    fn new(entries: Vec<Entry>) -> ItemList {
+        let names = entries.iter()
+            .map(|entry| match entry {
+                Entry::Name(name) | Entry::Item { name, .. } => name.clone(),
+            })
+            .collect();
+        let mut list = ItemList { names, entries, weights: Vec::new(), uniform: true };
+        list.restrict(None);
+        list
+    }
+
+    /// Leaves out the items not available yet as of a version or date, all being available without one
+    pub fn restrict(&mut self, as_of: Option<&str>) {
+        self.weights = self.entries.iter()
+            .map(|entry| match entry {
+                Entry::Name(_) => 1.,
+                Entry::Item { available_from: Some(from), .. }
+                    if as_of.is_some_and(|as_of| compare_versions(as_of, from) == Ordering::Less) => 0.,
+                Entry::Item { weight, .. } => weight.unwrap_or(1.),
+            })
+            .collect();
+        self.uniform = self.weights.iter().all(|&weight| weight == 1.);
+    }
+
+    /// Weight of the item at `index`, as restricted
+    pub fn weight(&self, index: usize) -> f64 {
+        self.weights[index]
+    }
+
+    /// Weights as written in the pool configuration, with where they are, for validation
+    pub fn configured(&self) -> impl Iterator<Item = (&str, Option<f64>, Option<&str>)> {
+        self.entries.iter().map(|entry| match entry {
+            Entry::Name(name) => (name.as_str(), None, None),
+            Entry::Item { name, weight, available_from } => (name.as_str(), *weight, available_from.as_deref()),
+        })
+    }
+
+    /// Index of an item picked at random by weight
+    pub fn pick(&self) -> usize {
+        if self.uniform {
+            return rng::gen_range(0..self.names.len());
+        }
+        let total: f64 = self.weights.iter().sum();
+        let mut remaining = rng::random::<f64>() * total;
+        for (index, weight) in self.weights.iter().enumerate() {
+            if remaining < *weight {
+                return index;
+            }
+            remaining -= weight;
+        }
+        // Rounding left the draw past the end, which belongs to the last item that can be picked
+        self.weights.iter().rposition(|&weight| weight > 0.).expect("No item can be picked, which validation rules out")
+    }
+}
+
+impl Deref for ItemList {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.names
+    }
+}
+
+impl Serialize for ItemList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.entries.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ItemList, D::Error> {
+        Ok(ItemList::new(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(json: &str) -> ItemList {
+        serde_json::from_str(json).expect("Invalid item list")
+    }
+
+    #[test]
+    fn versions_compare_number_by_number() {
+        assert_eq!(compare_versions("4.10", "4.9"), Ordering::Greater);
+        assert_eq!(compare_versions("4.9", "4.10"), Ordering::Less);
+        assert_eq!(compare_versions("4", "4.0"), Ordering::Equal);
+        assert_eq!(compare_versions("4.0.1", "4.0"), Ordering::Greater);
+        assert_eq!(compare_versions("10.0", "9.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2023-10-18", "2023-9-30"), Ordering::Greater);
+        assert_eq!(compare_versions("2023-10", "2023-10-01"), Ordering::Less);
+    }
+
+    #[test]
+    fn items_not_available_yet_are_never_picked() {
+        let mut items = list(r#"["A", {"name": "B", "available_from": "4.10"}, {"name": "C", "available_from": "4.2", "weight": 2}]"#);
+        assert_eq!((0..3).map(|i| items.weight(i)).collect::<Vec<_>>(), [1., 1., 2.]);
+        items.restrict(Some("4.9"));
+        assert_eq!((0..3).map(|i| items.weight(i)).collect::<Vec<_>>(), [1., 0., 2.]);
+        rng::seed(7);
+        assert!((0..10_000).all(|_| items.pick() != 1));
+        items.restrict(Some("4.10"));
+        assert_eq!(items.weight(1), 1.);
+        items.restrict(Some("4.1"));
+        assert_eq!((0..3).map(|i| items.weight(i)).collect::<Vec<_>>(), [1., 0., 0.]);
+        assert!((0..1_000).all(|_| items.pick() == 0));
+    }
+
+    #[test]
+    fn picks_follow_the_weights() {
+        let items = list(r#"[{"name": "A", "weight": 1}, {"name": "B", "weight": 3}, {"name": "C", "weight": 0}, {"name": "D", "weight": 0.5}, "E"]"#);
+        let weights = [1., 3., 0., 0.5, 1.];
+        let total: f64 = weights.iter().sum();
+        let picks = 200_000;
+        let mut counts = [0u32; 5];
+        rng::seed(42);
+        for _ in 0..picks {
+            counts[items.pick()] += 1;
+        }
+        for (count, weight) in counts.iter().zip(weights) {
+            let expected = weight / total;
+            let error = (expected * (1. - expected) / picks as f64).sqrt();
+            let share = *count as f64 / picks as f64;
+            assert!((share - expected).abs() <= 4. * error, "share {} against weight share {}", share, expected);
+        }
+        assert_eq!(counts[2], 0);
+    }
+}
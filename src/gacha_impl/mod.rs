@@ -1,459 +1,640 @@
+pub mod explain;
+pub mod fast;
+pub mod item_list;
+pub mod odds;
+pub mod validation;
+
 use super::gacha_traits::*;
+use super::gacha_traits::rule_sets::*;
 use super::gacha_traits::gacha_enums::*;
-use serde::{Deserialize, Serialize};
-use rand::Rng;
-
-pub struct NormalGachaState {
-    since_last_5star: u32,
-    since_last_4star: u32,
-    since_last_5star_character: u32,
-    since_last_5star_weapon: u32,
-    since_last_4star_character: u32,
-    since_last_4star_weapon: u32,
+use crate::common::rng;
+use serde::{Deserialize, Deserializer, Serialize};
+use item_list::ItemList;
+use std::fs;
+use validation::*;
+
+/// Rolls for a featured item of the tier `level`, tracing the roll
+fn roll_featured(featured: &FeaturedRules, level: ItemLevel, last_is_up: &mut bool, lost_streak: &mut u32) -> UpType {
+    let (guaranteed, streak) = (!*last_is_up, *lost_streak);
+    let (up_type, roll) = featured.roll(last_is_up, lost_streak);
+    explain::record(|trace| trace.featured = Some(explain::FeaturedTrace {
+        level,
+        guaranteed,
+        lost_streak: streak,
+        up_probability: featured.up_probability(streak),
+        roll,
+        won: matches!(up_type, UpType::Up(_)),
+    }));
+    up_type
 }
 
-pub struct CharacterGachaState {
-    since_last_5star: u32,
-    since_last_4star: u32,
-    since_last_4star_character: u32,
-    since_last_4star_weapon: u32,
-    last_5star_is_up: bool,
-    last_4star_is_up: bool,
+/// Rolls for the type of an off-banner item of the tier `level`, tracing the roll
+fn roll_balance(balance: &BalanceRules, level: ItemLevel, base_probability: f64, since_last_character: &mut u32, since_last_weapon: &mut u32) -> ItemType {
+    let (count_character, count_weapon) = (*since_last_character + 1, *since_last_weapon + 1);
+    let (item_type, roll) = balance.roll(base_probability, since_last_character, since_last_weapon);
+    explain::record(|trace| trace.balance = Some(explain::BalanceTrace {
+        level,
+        count_character,
+        count_weapon,
+        p_character: balance.probability(base_probability, count_character),
+        p_weapon: balance.probability(base_probability, count_weapon),
+        roll,
+        item_type,
+    }));
+    item_type
 }
 
-pub struct WeaponGachaState {
-    since_last_5star: u32,
-    since_last_4star: u32,
-    since_last_4star_character: u32,
-    since_last_4star_weapon: u32,
-    last_5star_is_up: bool,
-    last_4star_is_up: bool,
-    want_5star_state: WantState,
+/// A banner simulated from its rules
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GachaBanner {
+    pub rules: BannerRules,
+    pub state: GachaState,
 }
 
-impl Gacha5StarInfo for NormalGachaState {
-    const BASE_PROBABILITY_5STAR: f64 = 0.006;
-    const THRESHOLD_5STAR: u32 = 73;
-    const MAX_5STAR: u32 = 90;
-
-    fn get_since_last_5star(&self) -> u32 {
-        self.since_last_5star
-    }
-
-    fn set_since_last_5star(&mut self, count: u32) {
-        self.since_last_5star = count;
+impl GachaBanner {
+    pub fn new(rules: BannerRules) -> GachaBanner {
+        let mut state = GachaState::new();
+        if rules.path.is_some() {
+            state.want_5star_state = WantState::Want(0, 0);
+        }
+        GachaBanner { rules, state }
+    }
+
+    pub fn chart_path(&mut self, index: Option<u32>) {
+        self.state.want_5star_state = match index {
+            Some(index) => WantState::Want(index, 0),
+            None => WantState::None,
+        };
+    }
+
+    /// Rolls the level of a pull, unless `forced`, which a rolled higher level overrides if `or_higher`
+    fn get_item_level(&mut self, forced: Option<ItemLevel>, or_higher: bool) -> ItemLevel {
+        let state = &mut self.state;
+        let count_5star = state.since_last_5star + 1;
+        let count_4star = state.since_last_4star + 1;
+        let p_5star = self.rules.star5.pity.probability(count_5star);
+        let p_4star = self.rules.star4.pity.probability(count_4star);
+
+        let rnd_num: f64 = rng::random();
+        let rolled = if rnd_num < p_5star {
+            ItemLevel::Star5
+        } else if rnd_num < p_5star + p_4star {
+            ItemLevel::Star4
+        } else {
+            ItemLevel::Star3
+        };
+        let forced = forced.filter(|&forced| !(or_higher && rolled > forced));
+        let level = forced.unwrap_or(rolled);
+        explain::record(|trace| {
+            trace.prob_5star = p_5star;
+            trace.prob_4star = p_4star;
+            trace.roll = rnd_num;
+            trace.forced = forced;
+            trace.level = level;
+        });
+        match level {
+            ItemLevel::Star5 => {
+                state.since_last_5star = 0;
+                state.since_last_4star = count_4star;
+            }
+            ItemLevel::Star4 => {
+                state.since_last_5star = count_5star;
+                state.since_last_4star = 0;
+            }
+            ItemLevel::Star3 => {
+                state.since_last_5star = count_5star;
+                state.since_last_4star = count_4star;
+            }
+        }
+        level
+    }
+
+    /// Scripted items still to be given on the next pull or a later one
+    fn pending_scripts(&self) -> impl Iterator<Item = &ScriptedRules> {
+        let state = &self.state;
+        let obtained = move |level| match level {
+            ItemLevel::Star5 => state.since_last_5star < state.pulls,
+            ItemLevel::Star4 => state.since_last_4star < state.pulls,
+            ItemLevel::Star3 => state.pulls > 0,
+        };
+        self.rules.script.iter().filter(move |script| {
+            let obtained = [ItemLevel::Star5, ItemLevel::Star4, ItemLevel::Star3].into_iter()
+                .filter(|&level| level == script.level || script.or_higher && level > script.level)
+                .any(obtained);
+            !obtained && state.pulls < script.by
+        })
+    }
+
+    fn get_5star_up_type(&mut self) -> UpType {
+        let GachaBanner { rules, state } = self;
+        let roll = |state: &mut GachaState| match &rules.star5.featured {
+            Some(featured) => roll_featured(featured, ItemLevel::Star5, &mut state.last_5star_is_up, &mut state.lost_5star_streak),
+            None => UpType::NonUp,
+        };
+        match (&rules.path, state.want_5star_state) {
+            (Some(path), WantState::Want(index, points)) if points >= path.max_fate_points => {
+                explain::record(|trace| trace.path = Some(explain::PathTrace {
+                    charted: index,
+                    fate_points: points,
+                    max_fate_points: path.max_fate_points,
+                    fated: true,
+                }));
+                state.last_5star_is_up = true;
+                state.want_5star_state = WantState::Want(index, 0);
+                UpType::Up(index)
+            }
+            (Some(path), WantState::Want(want_index, points)) => {
+                explain::record(|trace| trace.path = Some(explain::PathTrace {
+                    charted: want_index,
+                    fate_points: points,
+                    max_fate_points: path.max_fate_points,
+                    fated: false,
+                }));
+                let up_type = match roll(state) {
+                    UpType::Up(_) if path.course => UpType::Up(want_index),
+                    up_type => up_type,
+                };
+                let points = match up_type {
+                    UpType::Up(index) if index == want_index => 0,
+                    _ => points + 1,
+                };
+                state.want_5star_state = WantState::Want(want_index, points);
+                up_type
+            }
+            _ => roll(state),
+        }
     }
-}
 
-impl Gacha4StarInfo for NormalGachaState {
-    const BASE_PROBABILITY_4STAR: f64 = 0.051;
-    const THRESHOLD_4STAR: u32 = 8;
-    const MAX_4STAR: u32 = 10;
-
-    fn get_since_last_4star(&self) -> u32 {
-        self.since_last_4star
-    }
+    fn pull_one(&mut self) -> GachaResult {
+        let pending: Vec<ScriptedRules> = self.pending_scripts().cloned().collect();
+        let forced = pending.iter().find(|script| script.by == self.state.pulls + 1);
+        explain::begin(self);
+        let level = self.get_item_level(forced.map(|script| script.level), forced.is_some_and(|script| script.or_higher));
+        let scripted = pending.iter()
+            .find(|script| script.level == level)
+            .and_then(|script| script.featured);
+        explain::record(|trace| trace.scripted = scripted);
+        let result = self.resolve(level, scripted);
+        explain::finish(&result);
+        result
+    }
+
+    /// Counts a pull whose level was already rolled and picks its item
+    fn resolve(&mut self, level: ItemLevel, scripted: Option<u32>) -> GachaResult {
+        self.state.pulls += 1;
+        if self.rules.spark.is_some() {
+            self.state.spark_points += 1;
+        }
 
-    fn set_since_last_4star(&mut self, count: u32) {
-        self.since_last_4star = count;
+        match level {
+            ItemLevel::Star3 => {
+                let state = &mut self.state;
+                state.since_last_5star_character += 1;
+                state.since_last_5star_weapon += 1;
+                state.since_last_4star_character += 1;
+                state.since_last_4star_weapon += 1;
+                GachaResult::Other3Star
+            }
+            ItemLevel::Star4 => {
+                let GachaBanner { rules, state } = self;
+                state.since_last_5star_character += 1;
+                state.since_last_5star_weapon += 1;
+
+                let up_type = match (scripted, &rules.star4.featured) {
+                    (Some(index), _) => UpType::Up(index),
+                    (None, Some(featured)) => roll_featured(featured, ItemLevel::Star4, &mut state.last_4star_is_up, &mut state.lost_4star_streak),
+                    (None, None) => UpType::NonUp,
+                };
+                match up_type {
+                    UpType::Up(index) => {
+                        match rules.item_type {
+                            ItemType::Character => {
+                                state.since_last_4star_character = 0;
+                                state.since_last_4star_weapon += 1;
+                            }
+                            ItemType::Weapon => {
+                                state.since_last_4star_character += 1;
+                                state.since_last_4star_weapon = 0;
+                            }
+                        }
+                        GachaResult::Up4Star(index)
+                    }
+                    UpType::NonUp => {
+                        let item_type = match &rules.star4.balance {
+                            Some(balance) => roll_balance(balance, ItemLevel::Star4, rules.star4.pity.base_probability, &mut state.since_last_4star_character, &mut state.since_last_4star_weapon),
+                            None => rules.item_type,
+                        };
+                        GachaResult::Other4Star(item_type)
+                    }
+                }
+            }
+            ItemLevel::Star5 => {
+                self.state.since_last_4star_character += 1;
+                self.state.since_last_4star_weapon += 1;
+
+                let up_type = match scripted {
+                    Some(index) => UpType::Up(index),
+                    None => self.get_5star_up_type(),
+                };
+                match up_type {
+                    UpType::Up(index) => GachaResult::Up5Star(index),
+                    UpType::NonUp => {
+                        let GachaBanner { rules, state } = self;
+                        let item_type = match &rules.star5.balance {
+                            Some(balance) => roll_balance(balance, ItemLevel::Star5, rules.star5.pity.base_probability, &mut state.since_last_5star_character, &mut state.since_last_5star_weapon),
+                            None => rules.item_type,
+                        };
+                        GachaResult::Other5Star(item_type)
+                    }
+                }
+            }
+        }
     }
 }
 
-impl Balance5Star for NormalGachaState {
-    const BALANCE_THRESHOLD_5STAR: u32 = 146;
-
-    fn get_since_last_5star_character(&self) -> u32 {
-        self.since_last_5star_character
-    }
-
-    fn get_since_last_5star_weapon(&self) -> u32 {
-        self.since_last_5star_weapon
+impl Banner for GachaBanner {
+    fn pull(&mut self) -> GachaResult {
+        self.pull_one()
     }
 
-    fn set_since_last_5star_character(&mut self, count: u32) {
-        self.since_last_5star_character = count;
+    fn state(&self) -> &GachaState {
+        &self.state
     }
 
-    fn set_since_last_5star_weapon(&mut self, count: u32) {
-        self.since_last_5star_weapon = count;
+    fn exchange(&mut self, index: u32) -> Option<GachaResult> {
+        let spark = self.rules.spark.as_ref()?;
+        let featured = self.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+        if index >= featured || self.state.spark_points < spark.threshold {
+            return None;
+        }
+        self.state.spark_points -= spark.threshold;
+        Some(GachaResult::Up5Star(index))
     }
-}
 
-impl Balance4Star for NormalGachaState {
-    const BALANCE_THRESHOLD_4STAR: u32 = 17;
-
-    fn get_since_last_4star_character(&self) -> u32 {
-        self.since_last_4star_character
-    }
-    
-    fn get_since_last_4star_weapon(&self) -> u32 {
-        self.since_last_4star_weapon
+    fn next_banner(&mut self) {
+        if !self.rules.spark.as_ref().is_some_and(|spark| spark.carry_over) {
+            self.state.spark_points = 0;
+        }
     }
 
-    fn set_since_last_4star_character(&mut self, count: u32) {
-        self.since_last_4star_character = count;
+    fn reset(&mut self) {
+        let want_5star_state = match self.state.want_5star_state {
+            WantState::Want(index, _) => WantState::Want(index, 0),
+            WantState::None => WantState::None,
+        };
+        self.state = GachaState::new();
+        self.state.want_5star_state = want_5star_state;
     }
 
-    fn set_since_last_4star_weapon(&mut self, count: u32) {
-        self.since_last_4star_weapon = count;
+    fn rules(&self) -> &BannerRules {
+        &self.rules
     }
 }
 
-impl GeneralGachaMechanism for NormalGachaState {}
-
-impl Gacha5StarInfo for CharacterGachaState {
-    const BASE_PROBABILITY_5STAR: f64 = 0.006;
-    const THRESHOLD_5STAR: u32 = 73;
-    const MAX_5STAR: u32 = 90;
-
-    fn get_since_last_5star(&self) -> u32 {
-        self.since_last_5star
-    }
-
-    fn set_since_last_5star(&mut self, count: u32) {
-        self.since_last_5star = count;
+fn colorize(level: ItemLevel, name: &str) -> String {
+    match level {
+        ItemLevel::Star5 => format!("\x1b[01m\x1b[38;2;186;106;53m{}\x1b[0m\x1b[0m", name),
+        ItemLevel::Star4 => format!("\x1b[01m\x1b[38;2;160;90;215m{}\x1b[0m\x1b[0m", name),
+        ItemLevel::Star3 => name.to_string(),
     }
 }
 
-impl Gacha4StarInfo for CharacterGachaState {
-    const BASE_PROBABILITY_4STAR: f64 = 0.051;
-    const THRESHOLD_4STAR: u32 = 8;
-    const MAX_4STAR: u32 = 10;
-
-    fn get_since_last_4star(&self) -> u32 {
-        self.since_last_4star
-    }
-
-    fn set_since_last_4star(&mut self, count: u32) {
-        self.since_last_4star = count;
+impl GachaResult {
+    pub fn get_item_level(&self) -> ItemLevel {
+        match self {
+            Self::Up5Star(_) | Self::Other5Star(_) => ItemLevel::Star5,
+            Self::Up4Star(_) | Self::Other4Star(_) => ItemLevel::Star4,
+            Self::Other3Star => ItemLevel::Star3,
+        }
     }
-}
 
-impl Up5Star for CharacterGachaState {
-    const UP_PROBABILITY_5STAR: f64 = 0.5;
-    const UP_5STAR_NUM: u32 = 1;
-
-    fn get_last_5star_is_up(&self) -> bool {
-        self.last_5star_is_up
+    pub fn is_up(&self) -> bool {
+        matches!(self, Self::Up5Star(_) | Self::Up4Star(_))
     }
 
-    fn set_last_5star_is_up(&mut self, is_up: bool) {
-        self.last_5star_is_up = is_up;
+    pub fn get_item_type(&self, rules: &BannerRules, pool: &BannerPool) -> ItemType {
+        match self {
+            Self::Up5Star(index) => pool.get_up_5star_type(*index, rules.item_type),
+            Self::Up4Star(_) => rules.item_type,
+            Self::Other5Star(item_type) | Self::Other4Star(item_type) => *item_type,
+            Self::Other3Star => ItemType::Weapon,
+        }
     }
-}
-
-impl Up4Star for CharacterGachaState {
-    const UP_PROBABILITY_4STAR: f64 = 0.5;
-    const UP_4STAR_NUM: u32 = 3;
 
-    fn get_last_4star_is_up(&self) -> bool {
-        self.last_4star_is_up
+    /// Off-banner pool list the item is drawn from, as its field name and items
+    pub fn off_banner_list<'a>(&self, pool: &'a BannerPool) -> Option<(&'static str, &'a ItemList)> {
+        match self {
+            Self::Up5Star(_) | Self::Up4Star(_) => None,
+            Self::Other5Star(ItemType::Weapon) if !pool.other_5star_weapon.is_empty() => Some(("other_5star_weapon", &pool.other_5star_weapon)),
+            Self::Other5Star(_) => Some(("other_5star", &pool.other_5star)),
+            Self::Other4Star(ItemType::Character) => Some(("other_4star_character", &pool.other_4star_character)),
+            Self::Other4Star(ItemType::Weapon) => Some(("other_4star_weapon", &pool.other_4star_weapon)),
+            Self::Other3Star => Some(("other_3star", &pool.other_3star)),
+        }
     }
 
-    fn set_last_4star_is_up(&mut self, is_up: bool) {
-        self.last_4star_is_up = is_up;
+    /// Pool list the item is drawn from, as its field name and items
+    pub fn pool_list<'a>(&self, pool: &'a BannerPool) -> (&'static str, &'a [String]) {
+        match self {
+            Self::Up5Star(index) if *index as usize >= pool.up_5star.len() => ("up_5star_weapon", &pool.up_5star_weapon),
+            Self::Up5Star(_) => ("up_5star", &pool.up_5star),
+            Self::Up4Star(_) => ("up_4star", &pool.up_4star),
+            _ => self.off_banner_list(pool).map(|(list, items)| (list, &items[..])).expect("Off-banner item without a list"),
+        }
     }
-}
 
-impl Balance4Star for CharacterGachaState {
-    const BALANCE_THRESHOLD_4STAR: u32 = 17;
-
-    fn get_since_last_4star_character(&self) -> u32 {
-        self.since_last_4star_character
-    }
-    
-    fn get_since_last_4star_weapon(&self) -> u32 {
-        self.since_last_4star_weapon
+    /// Index of the item in its `pool_list`, off-banner items being picked at random by weight
+    pub fn pick_item_index(&self, pool: &BannerPool) -> usize {
+        match (self, self.off_banner_list(pool)) {
+            (_, Some((_, items))) => items.pick(),
+            (Self::Up5Star(index), _) if *index as usize >= pool.up_5star.len() => *index as usize - pool.up_5star.len(),
+            (Self::Up5Star(index), _) | (Self::Up4Star(index), _) => *index as usize,
+            _ => unreachable!("Off-banner item without a list"),
+        }
     }
 
-    fn set_since_last_4star_character(&mut self, count: u32) {
-        self.since_last_4star_character = count;
+    pub fn pick_item_name<'a>(&self, pool: &'a BannerPool) -> &'a str {
+        &self.pool_list(pool).1[self.pick_item_index(pool)]
     }
 
-    fn set_since_last_4star_weapon(&mut self, count: u32) {
-        self.since_last_4star_weapon = count;
+    pub fn get_item_name(& self, pool: & BannerPool) -> String {
+        colorize(self.get_item_level(), self.pick_item_name(pool))
     }
 }
 
-impl GeneralGachaMechanism for CharacterGachaState {}
-
-impl Gacha5StarInfo for WeaponGachaState {
-    const BASE_PROBABILITY_5STAR: f64 = 0.007;
-    const THRESHOLD_5STAR: u32 = 62;
-    const MAX_5STAR: u32 = 80;
-
-    fn get_since_last_5star(&self) -> u32 {
-        self.since_last_5star
-    }
-
-    fn set_since_last_5star(&mut self, count: u32) {
-        self.since_last_5star = count;
-    }
+/// What a pool configuration says about an item found by name
+pub struct PoolItem {
+    pub level: ItemLevel,
+    pub item_type: ItemType,
+    pub up_index: Option<u32>,
 }
 
-impl Gacha4StarInfo for WeaponGachaState {
-    const BASE_PROBABILITY_4STAR: f64 = 0.06;
-    const THRESHOLD_4STAR: u32 = 7;
-    const MAX_4STAR: u32 = 10;
+fn find_in(items: &[String], name: &str) -> Option<u32> {
+    items.iter().position(|item| item == name).map(|index| index as u32)
+}
 
-    fn get_since_last_4star(&self) -> u32 {
-        self.since_last_4star
+/// Accepts either a single name or a list of names
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
     }
 
-    fn set_since_last_4star(&mut self, count: u32) {
-        self.since_last_4star = count;
-    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    })
 }
 
-impl Up5Star for WeaponGachaState {
-    const UP_PROBABILITY_5STAR: f64 = 0.75;
-    const UP_5STAR_NUM: u32 = 2;
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BannerPool {
+    #[serde(deserialize_with = "one_or_many")]
+    up_5star: Vec<String>,
+    /// Featured 5 star weapons on banners featuring characters too, indexed after `up_5star`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    up_5star_weapon: Vec<String>,
+    up_4star: Vec<String>,
+    other_5star: ItemList,
+    /// Off-banner 5 star weapons on banners balancing characters and weapons,
+    /// `other_5star` then holding the characters
+    #[serde(default, skip_serializing_if = "<[String]>::is_empty")]
+    other_5star_weapon: ItemList,
+    other_4star_character: ItemList,
+    other_4star_weapon: ItemList,
+    other_3star: ItemList,
+    /// Replaces the 5 star pity of the banner the pool is pulled on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pity_5star: Option<PityRules>,
+    /// Replaces the 4 star pity of the banner the pool is pulled on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pity_4star: Option<PityRules>,
+    /// Replaces the featured 5 star rules, e.g. to switch to Capturing Radiance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    featured_5star: Option<FeaturedRules>,
+    /// Adds a spark to the banner the pool is pulled on, or replaces its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spark: Option<SparkRules>,
+}
 
-    fn get_last_5star_is_up(&self) -> bool {
-        self.last_5star_is_up
+impl BannerPool {
+    /// Off-banner lists by field name
+    pub fn off_banner_lists(&self) -> [(&'static str, &ItemList); 5] {
+        [
+            ("other_5star", &self.other_5star),
+            ("other_5star_weapon", &self.other_5star_weapon),
+            ("other_4star_character", &self.other_4star_character),
+            ("other_4star_weapon", &self.other_4star_weapon),
+            ("other_3star", &self.other_3star),
+        ]
+    }
+
+    /// Leaves out the off-banner items not available yet as of a version or date
+    pub fn restrict(&mut self, as_of: Option<&str>) {
+        for list in [
+            &mut self.other_5star,
+            &mut self.other_5star_weapon,
+            &mut self.other_4star_character,
+            &mut self.other_4star_weapon,
+            &mut self.other_3star,
+        ] {
+            list.restrict(as_of);
+        }
     }
 
-    fn set_last_5star_is_up(&mut self, is_up: bool) {
-        self.last_5star_is_up = is_up;
+    /// Applies the rule overrides of the pool to the rules of a banner
+    pub fn rules(&self, mut rules: BannerRules) -> BannerRules {
+        if let Some(pity) = &self.pity_5star {
+            rules.star5.pity = pity.clone();
+        }
+        if let Some(pity) = &self.pity_4star {
+            rules.star4.pity = pity.clone();
+        }
+        if let Some(featured) = &self.featured_5star {
+            rules.star5.featured = Some(featured.clone());
+        }
+        if let Some(spark) = &self.spark {
+            rules.spark = Some(spark.clone());
+        }
+        rules
     }
-}
 
-impl Up4Star for WeaponGachaState {
-    const UP_PROBABILITY_4STAR: f64 = 0.75;
-    const UP_4STAR_NUM: u32 = 5;
+    pub fn get_up_5star_name(&self, index: u32) -> &str {
+        let index = index as usize;
+        match self.up_5star.get(index) {
+            Some(name) => name,
+            None => &self.up_5star_weapon[index - self.up_5star.len()],
+        }
+    }
 
-    fn get_last_4star_is_up(&self) -> bool {
-        self.last_4star_is_up
+    /// Type of a featured 5 star, `item_type` unless it is listed in `up_5star_weapon`
+    pub fn get_up_5star_type(&self, index: u32, item_type: ItemType) -> ItemType {
+        if index as usize >= self.up_5star.len() {
+            ItemType::Weapon
+        } else {
+            item_type
+        }
     }
 
-    fn set_last_4star_is_up(&mut self, is_up: bool) {
-        self.last_4star_is_up = is_up;
+    /// Looks an item up by name, featured and off-banner 5 stars being of `item_type`
+    pub fn find_item(&self, name: &str, item_type: ItemType) -> Option<PoolItem> {
+        let (level, item_type, up_index) = if let Some(index) = find_in(&self.up_5star, name) {
+            (ItemLevel::Star5, item_type, Some(index))
+        } else if let Some(index) = find_in(&self.up_5star_weapon, name) {
+            (ItemLevel::Star5, ItemType::Weapon, Some(self.up_5star.len() as u32 + index))
+        } else if let Some(index) = find_in(&self.up_4star, name) {
+            (ItemLevel::Star4, item_type, Some(index))
+        } else if find_in(&self.other_5star, name).is_some() {
+            (ItemLevel::Star5, item_type, None)
+        } else if find_in(&self.other_5star_weapon, name).is_some() {
+            (ItemLevel::Star5, ItemType::Weapon, None)
+        } else if find_in(&self.other_4star_character, name).is_some() {
+            (ItemLevel::Star4, ItemType::Character, None)
+        } else if find_in(&self.other_4star_weapon, name).is_some() {
+            (ItemLevel::Star4, ItemType::Weapon, None)
+        } else if find_in(&self.other_3star, name).is_some() {
+            (ItemLevel::Star3, ItemType::Weapon, None)
+        } else {
+            return None;
+        };
+        Some(PoolItem { level, item_type, up_index })
     }
 }
 
-impl Want5Star for WeaponGachaState {
-    const WANT_5STAR_MAX: u32 = 2;
+#[derive(Serialize, Deserialize)]
+pub struct Pools {
+    /// Name of the rule set the banners follow, the default one when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<String>,
+    /// Version or date simulated, leaving out the items only available after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<String>,
+    pub character1: BannerPool,
+    pub character2: BannerPool,
+    pub weapon: BannerPool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chronicled: Option<BannerPool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beginner: Option<BannerPool>,
 }
 
-impl Balance4Star for WeaponGachaState {
-    const BALANCE_THRESHOLD_4STAR: u32 = 14;
-
-    fn get_since_last_4star_character(&self) -> u32 {
-        self.since_last_4star_character
+impl Pools {
+    /// Rule set named by the configuration, which validation makes sure exists
+    pub fn rule_set(&self) -> RuleSet {
+        let name = self.rules.as_deref().unwrap_or(DEFAULT_RULE_SET);
+        RuleSet::named(name).unwrap_or_else(|| panic!("Unknown rule set: {}", name))
     }
-    
-    fn get_since_last_4star_weapon(&self) -> u32 {
-        self.since_last_4star_weapon
+
+    /// Rules of the character banner, which both character pools share
+    pub fn character_rules(&self) -> BannerRules {
+        self.character1.rules(self.rule_set().character)
     }
 
-    fn set_since_last_4star_character(&mut self, count: u32) {
-        self.since_last_4star_character = count;
+    pub fn weapon_rules(&self) -> BannerRules {
+        self.weapon.rules(self.rule_set().weapon)
     }
 
-    fn set_since_last_4star_weapon(&mut self, count: u32) {
-        self.since_last_4star_weapon = count;
+    /// Rules of the Chronicled Wish, if both the rule set and the configuration have one
+    pub fn chronicled_rules(&self) -> Option<BannerRules> {
+        Some(self.chronicled.as_ref()?.rules(self.rule_set().chronicled?))
     }
-}
 
-impl GeneralGachaMechanism for WeaponGachaState {}
+    /// Rules of the Beginners' Wish, if both the rule set and the configuration have one
+    pub fn beginner_rules(&self) -> Option<BannerRules> {
+        Some(self.beginner.as_ref()?.rules(self.rule_set().beginner?))
+    }
 
-impl NormalGachaState {
-    pub fn simulate_normal_gacha(&mut self) -> NormalGachaType {
-        match self.get_item_level() {
-            ItemLevel::Star3 => {
-                self.since_last_5star_character += 1;
-                self.since_last_5star_weapon += 1;
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-                NormalGachaType::Other3Star
-            }
-            ItemLevel::Star4 => {
-                self.since_last_5star_character += 1;
-                self.since_last_5star_weapon += 1;
-    
-                match self.get_4star_balance_type() {
-                    ItemType::Character => {
-                        NormalGachaType::Character4Star
-                    }
-                    ItemType::Weapon => {
-                        NormalGachaType::Weapon4Star
-                    }
-                }
-            }
-            ItemLevel::Star5 => {
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-    
-                match self.get_5star_balance_type() {
-                    ItemType::Character => {
-                        NormalGachaType::Character5Star
-                    }
-                    ItemType::Weapon => {
-                        NormalGachaType::Weapon5Star
-                    }
-                }
-            }
-        }
+    pub fn standard_rules(&self) -> BannerRules {
+        self.rule_set().standard
     }
-}
 
-impl CharacterGachaState {
-    pub fn new() -> CharacterGachaState {
-        CharacterGachaState {
-            since_last_5star: 0,
-            since_last_4star: 0,
-            since_last_4star_character: 0,
-            since_last_4star_weapon: 0,
-            last_5star_is_up: true,
-            last_4star_is_up: true,
+    /// Simulates as of a version or date, leaving out the off-banner items only available after it
+    pub fn set_as_of(&mut self, as_of: Option<String>) {
+        self.as_of = as_of;
+        let pools = [&mut self.character1, &mut self.character2, &mut self.weapon].into_iter()
+            .chain(self.chronicled.as_mut())
+            .chain(self.beginner.as_mut());
+        for pool in pools {
+            pool.restrict(self.as_of.as_deref());
         }
     }
 
-    pub fn simulate_character_gacha(&mut self) -> CharacterGachaType {
-        match self.get_item_level() {
-            ItemLevel::Star3 => {
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-                CharacterGachaType::Other3Star
+    /// Loads and validates a pool configuration file under the `rules` set and as of the
+    /// `as_of` version or date when given, printing the diagnostics and exiting if it
+    /// contains errors
+    pub fn from_file(file_path: &str, rules: Option<&str>, as_of: Option<&str>) -> Pools {
+        let (pools, diagnostics) = validate_pools(file_path, rules, as_of);
+        let errors: Vec<_> = diagnostics.iter().filter(|d| d.severity == Severity::Error).collect();
+        match pools {
+            Some(mut pools) if errors.is_empty() => {
+                pools.set_as_of(pools.as_of.clone());
+                pools
             }
-            ItemLevel::Star4 => {
-                match self.get_4star_up_type() {
-                    UpType::Up(index) => {
-                        self.since_last_4star_character = 0;
-                        self.since_last_4star_weapon += 1;
-                        CharacterGachaType::Up4Star(index)
-                    }
-                    UpType::NonUp => {
-                        match self.get_4star_balance_type() {
-                            ItemType::Character => {
-                                CharacterGachaType::Other4StarCharacter
-                            }
-                            ItemType::Weapon => {
-                                CharacterGachaType::Other4StarWeapon
-                            }
-                        }
-                    }
-                }
-            }
-            ItemLevel::Star5 => {
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-
-                match self.get_5star_up_type() {
-                    UpType::Up(_) => {
-                        CharacterGachaType::Up5Star
-                    }
-                    UpType::NonUp => {
-                        CharacterGachaType::Other5Star
-                    }
+            _ => {
+                for error in errors {
+                    eprintln!("{}", error);
                 }
+                std::process::exit(1);
             }
         }
     }
 }
 
-impl WeaponGachaState {
-    pub fn new() -> WeaponGachaState {
-        WeaponGachaState {
-            since_last_5star: 0,
-            since_last_4star: 0,
-            since_last_4star_character: 0,
-            since_last_4star_weapon: 0,
-            last_5star_is_up: true,
-            last_4star_is_up: true,
-            want_5star_state: WantState::Want(0, 0),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Results of 1000 pulls from seed 42 on each banner, then the next random `u64`, as
+    /// `CharacterGachaState`, `WeaponGachaState` and `NormalGachaState` gave them before the
+    /// rules-driven engine replaced them
+    const LEGACY_SEED_42: &str = include_str!("testdata/legacy_seed42.txt");
+
+    fn code(result: &GachaResult) -> String {
+        let item_type = |item_type: &ItemType| match item_type {
+            ItemType::Character => "C",
+            ItemType::Weapon => "W",
+        };
+        match result {
+            GachaResult::Up5Star(index) => format!("5U{}", index),
+            GachaResult::Up4Star(index) => format!("4U{}", index),
+            GachaResult::Other5Star(t) => format!("5{}", item_type(t)),
+            GachaResult::Other4Star(t) => format!("4{}", item_type(t)),
+            GachaResult::Other3Star => String::from("3"),
         }
     }
 
-    pub fn simulate_weapon_gacha(&mut self) -> WeaponGachaType {
-        match self.get_item_level() {
-            ItemLevel::Star3 => {
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-                WeaponGachaType::Other3Star
-            }
-            ItemLevel::Star4 => {
-                match self.get_4star_up_type() {
-                    UpType::Up(index) => {
-                        self.since_last_4star_character += 1;
-                        self.since_last_4star_weapon = 0;
-                        WeaponGachaType::Up4Star(index)
-                    }
-                    UpType::NonUp => {
-                        match self.get_4star_balance_type() {
-                            ItemType::Character => {
-                                WeaponGachaType::Other4StarCharacter
-                            }
-                            ItemType::Weapon => {
-                                WeaponGachaType::Other4StarWeapon
-                            }
-                        }
-                    }
-                }
-            }
-            ItemLevel::Star5 => {
-                self.since_last_4star_character += 1;
-                self.since_last_4star_weapon += 1;
-
-                match self.want_5star_state {
-                    WantState::Want(index, Self::WANT_5STAR_MAX) => {
-                        self.last_5star_is_up = true;
-                        self.want_5star_state = WantState::Want(index, 0);
-                        WeaponGachaType::Up5Star(index)
-                    }
-                    WantState::Want(want_index, curr) => {
-                        match self.get_5star_up_type() {
-                            UpType::Up(index) => {
-                                self.want_5star_state = WantState::Want(want_index, if index == want_index {0} else {curr + 1});
-                                WeaponGachaType::Up5Star(index)
-                            }
-                            UpType::NonUp => {
-                                self.want_5star_state = WantState::Want(want_index, curr + 1);
-                                WeaponGachaType::Other5Star
-                            }
-                        }
-                    }
-                    WantState::None => {
-                        match self.get_5star_up_type() {
-                            UpType::Up(index) => {
-                                WeaponGachaType::Up5Star(index)
-                            }
-                            UpType::NonUp => {
-                                WeaponGachaType::Other5Star
-                            }
-                        }
-                    }
-                }
-            }
+    #[test]
+    fn same_results_and_draws_as_legacy_states() {
+        let mut lines = LEGACY_SEED_42.lines();
+        for rules in [BannerRules::character(), BannerRules::weapon(), BannerRules::standard()] {
+            let (name, expected) = lines.next().unwrap().split_once(' ').unwrap();
+            let expected: Vec<&str> = expected.split(' ').collect();
+            rng::seed(42);
+            let mut banner = GachaBanner::new(rules);
+            let results: Vec<String> = (0..expected.len()).map(|_| code(&banner.pull())).collect();
+            assert_eq!(results, expected, "results of the {} banner", name);
+            let next = lines.next().unwrap().strip_prefix("next ").unwrap();
+            assert_eq!(rng::random::<u64>().to_string(), next, "draws made on the {} banner", name);
         }
     }
-}
 
-impl CharacterGachaType {
-    pub fn get_item_name(& self, pool: & CharacterPool) -> String {
-        match self {
-            Self::Up5Star => format!("\x1b[01m\x1b[38;2;186;106;53m{}\x1b[0m\x1b[0m", pool.up_5star),
-            Self::Up4Star(index) => format!("\x1b[01m\x1b[38;2;160;90;215m{}\x1b[0m\x1b[0m\x1b[0m", pool.up_4star[*index as usize]),
-            Self::Other5Star => format!("\x1b[01m\x1b[38;2;186;106;53m{}\x1b[0m\x1b[0m", pool.other_5star[rand::thread_rng().gen_range(0..pool.other_5star.len())]),
-            Self::Other4StarCharacter => format!("\x1b[01m\x1b[38;2;160;90;215m{}\x1b[0m\x1b[0m", pool.other_4star_character[rand::thread_rng().gen_range(0..pool.other_4star_character.len())]),
-            Self::Other4StarWeapon => format!("\x1b[01m\x1b[38;2;160;90;215m{}\x1b[0m\x1b[0m", pool.other_4star_weapon[rand::thread_rng().gen_range(0..pool.other_4star_weapon.len())]),
-            Self::Other3Star => format!("{}", pool.other_3star[rand::thread_rng().gen_range(0..pool.other_3star.len())]),
+    #[test]
+    fn higher_tier_meets_or_higher_script() {
+        rng::seed(42);
+        let rules = RuleSet::named("arknights").unwrap().character;
+        let (mut met_by_higher, mut forced_anyway) = (0, 0);
+        for _ in 0..20_000 {
+            let mut banner = GachaBanner::new(rules.clone());
+            let levels: Vec<ItemLevel> = (0..10).map(|_| banner.pull().get_item_level()).collect();
+            assert!(levels.iter().any(|&level| level >= ItemLevel::Star4), "no 5 star operator or better in the first ten pulls");
+            if levels[..9].contains(&ItemLevel::Star5) && !levels[..9].contains(&ItemLevel::Star4) {
+                met_by_higher += 1;
+                forced_anyway += (levels[9] == ItemLevel::Star4) as u32;
+            }
         }
+        // Pull 10 is then rolled as usual, a 5 star operator coming at its base rate of 8%
+        assert!(met_by_higher > 100);
+        assert!((forced_anyway as f64) < met_by_higher as f64 * 0.2, "{} of {} tenth pulls forced", forced_anyway, met_by_higher);
     }
 }
-
-#[derive(Serialize, Deserialize)]
-pub struct CharacterPool {
-    up_5star: String,
-    up_4star: Vec<String>,
-    other_5star: Vec<String>,
-    other_4star_character: Vec<String>,
-    other_4star_weapon: Vec<String>,
-    other_3star: Vec<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct Pools {
-    pub character1: CharacterPool,
-    pub character2: CharacterPool,
-}
@@ -0,0 +1,101 @@
+use super::*;
+
+/// Chances of the next pulls on a banner given its pity counters and guarantees
+pub struct NextPullOdds {
+    pub next_5star: f64,
+    pub next_4star: f64,
+    /// At least one of the tier within the next multi-pull
+    pub multi_5star: f64,
+    pub multi_4star: f64,
+    /// Chance the next 5 star is featured, `None` on banners without featured 5 stars
+    pub up_5star: Option<f64>,
+    /// Pulls until the one at soft pity, counting it, 0 once it is made, `None` without soft pity
+    pub to_soft_pity: Option<u32>,
+    /// Pulls until the one guaranteeing a 5 star, counting it
+    pub to_hard_pity: u32,
+}
+
+impl GachaBanner {
+    /// Chances of the level of the pull made with the given pity counters, as `get_item_level` rolls it
+    fn level_probabilities(&self, since_5star: u32, since_4star: u32) -> (f64, f64) {
+        let p_5star = self.rules.star5.pity.probability(since_5star + 1).clamp(0., 1.);
+        let p_4star = (p_5star + self.rules.star4.pity.probability(since_4star + 1)).clamp(0., 1.) - p_5star;
+        (p_5star, p_4star)
+    }
+
+    /// Odds of the next pull and of the next `multi` pulls, leaving scripted items out
+    pub fn odds(&self, multi: u32) -> NextPullOdds {
+        let state = &self.state;
+        let (next_5star, next_4star) = self.level_probabilities(state.since_last_5star, state.since_last_4star);
+
+        // A 5 star resets only its own counter, so no 5 star within the multi-pull is a plain product
+        let no_5star: f64 = (1..=multi)
+            .map(|i| 1. - self.rules.star5.pity.probability(state.since_last_5star + i).clamp(0., 1.))
+            .product();
+        // Whereas a 5 star can take the place of a 4 star, which needs both counters of every path without one
+        let mut paths = vec![(state.since_last_5star, state.since_last_4star, 1.)];
+        let mut multi_4star = 0.;
+        for _ in 0..multi {
+            let mut next = Vec::with_capacity(paths.len() * 2);
+            for (since_5star, since_4star, mass) in paths {
+                let (p_5star, p_4star) = self.level_probabilities(since_5star, since_4star);
+                multi_4star += mass * p_4star;
+                next.push((0, since_4star + 1, mass * p_5star));
+                next.push((since_5star + 1, since_4star + 1, mass * (1. - p_5star - p_4star)));
+            }
+            paths = next.into_iter().filter(|&(_, _, mass)| mass > 0.).collect();
+        }
+
+        let up_5star = self.rules.star5.featured.as_ref().map(|featured| {
+            let fated = match (&self.rules.path, state.want_5star_state) {
+                (Some(path), WantState::Want(_, points)) => points >= path.max_fate_points,
+                _ => false,
+            };
+            if fated || !state.last_5star_is_up { 1. } else { featured.up_probability(state.lost_5star_streak) }
+        });
+
+        let pity = &self.rules.star5.pity;
+        let soft_pity = pity.soft_pity();
+        let since_5star = state.since_last_5star;
+        NextPullOdds {
+            next_5star,
+            next_4star,
+            multi_5star: 1. - no_5star,
+            multi_4star,
+            up_5star,
+            to_soft_pity: (soft_pity < pity.max).then(|| soft_pity.saturating_sub(since_5star)),
+            to_hard_pity: pity.max.saturating_sub(since_5star).max(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pity_countdowns_step_down_by_one() {
+        let mut banner = GachaBanner::new(BannerRules::character());
+        let countdown = |banner: &mut GachaBanner, since: u32| {
+            banner.state.since_last_5star = since;
+            let odds = banner.odds(10);
+            (odds.to_soft_pity, odds.to_hard_pity)
+        };
+        assert_eq!(countdown(&mut banner, 0), (Some(74), 90));
+        assert_eq!(countdown(&mut banner, 72), (Some(2), 18));
+        assert_eq!(countdown(&mut banner, 73), (Some(1), 17));
+        assert_eq!(countdown(&mut banner, 74), (Some(0), 16));
+        assert_eq!(countdown(&mut banner, 89), (Some(0), 1));
+        for since in 1..90 {
+            let (soft, hard) = countdown(&mut banner, since);
+            let (previous_soft, previous_hard) = countdown(&mut banner, since - 1);
+            assert_eq!(soft, previous_soft.map(|pulls| pulls.saturating_sub(1)));
+            assert_eq!(hard, previous_hard - 1);
+        }
+        // The pull a countdown of 1 points at is the first with the raised probability
+        banner.state.since_last_5star = 72;
+        assert_eq!(banner.odds(1).next_5star, 0.006);
+        banner.state.since_last_5star = 73;
+        assert!(banner.odds(1).next_5star > 0.006);
+    }
+}
@@ -0,0 +1,529 @@
+use super::*;
+
+use item_list::compare_versions;
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a pool configuration file, located by line and column when possible
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file_path: String,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}: {}", self.file_path, line, column, severity, self.message),
+            None => write!(f, "{}: {}: {}", self.file_path, severity, self.message),
+        }
+    }
+}
+
+/// A JSON value with the byte offset of every key and array element in the source text
+enum Node {
+    Object(Vec<(String, usize, Node)>),
+    Array(Vec<(usize, Node)>),
+    String(String),
+    Other,
+}
+
+impl Node {
+    /// Parses the value starting at `*offset` of a source text serde_json already accepted
+    fn parse(source: &str, offset: &mut usize) -> Option<Node> {
+        let skip_whitespace = |offset: &mut usize| {
+            *offset += source[*offset..].len() - source[*offset..].trim_start().len();
+        };
+        skip_whitespace(offset);
+        let node = match source[*offset..].chars().next()? {
+            '{' | '[' => {
+                let is_object = source[*offset..].starts_with('{');
+                *offset += 1;
+                let mut fields = Vec::new();
+                let mut elements = Vec::new();
+                loop {
+                    skip_whitespace(offset);
+                    match source[*offset..].chars().next()? {
+                        '}' | ']' => break,
+                        ',' => *offset += 1,
+                        _ if is_object => {
+                            let start = *offset;
+                            let Node::String(key) = Node::parse(source, offset)? else {
+                                return None;
+                            };
+                            skip_whitespace(offset);
+                            *offset += 1;
+                            fields.push((key, start, Node::parse(source, offset)?));
+                        }
+                        _ => {
+                            skip_whitespace(offset);
+                            elements.push((*offset, Node::parse(source, offset)?));
+                        }
+                    }
+                }
+                *offset += 1;
+                if is_object { Node::Object(fields) } else { Node::Array(elements) }
+            }
+            '"' => {
+                let mut escaped = false;
+                let length = source[*offset + 1..].find(|c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })?;
+                let string = serde_json::from_str(&source[*offset..*offset + length + 2]).ok()?;
+                *offset += length + 2;
+                Node::String(string)
+            }
+            _ => {
+                let length = source[*offset..].find(|c: char| c == ',' || c == '}' || c == ']' || c.is_whitespace());
+                *offset += length.unwrap_or(source.len() - *offset);
+                Node::Other
+            }
+        };
+        Some(node)
+    }
+
+    /// Name of an item list element, either a bare name or an object with one
+    fn name(&self) -> Option<&str> {
+        match self {
+            Node::String(name) => Some(name),
+            Node::Object(fields) => fields.iter().find_map(|(key, _, value)| match (key.as_str(), value) {
+                ("name", Node::String(name)) => Some(name.as_str()),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Collects diagnostics, locating JSON keys and strings in the source text
+struct Validator<'a> {
+    file_path: &'a str,
+    source: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    /// Follows the path through the object keys and the names of item list elements, a name
+    /// repeated `n` times in a row meaning its `n`th element, and returns the line and column
+    /// of the deepest one found
+    fn locate(&self, path: &[&str]) -> Option<(usize, usize)> {
+        let root = Node::parse(self.source, &mut 0)?;
+        let mut node = &root;
+        let mut offset = None;
+        let mut rest = path;
+        while let Some((segment, after)) = rest.split_first() {
+            let repeats = rest.iter().take_while(|s| *s == segment).count();
+            let found = match node {
+                Node::Object(fields) => fields.iter()
+                    .find(|(key, _, _)| key == segment)
+                    .map(|(_, start, value)| (*start, value, after)),
+                Node::Array(elements) => elements.iter()
+                    .filter(|(_, element)| element.name() == Some(segment))
+                    .nth(repeats - 1)
+                    .map(|(start, element)| (*start, element, &rest[repeats..])),
+                _ => None,
+            };
+            let Some((start, next, after)) = found else {
+                break;
+            };
+            offset = Some(start);
+            node = next;
+            rest = after;
+        }
+        let offset = offset?;
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        Some((line, column))
+    }
+
+    fn report(&mut self, severity: Severity, path: &[&str], message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            file_path: self.file_path.to_string(),
+            location: self.locate(path),
+            message,
+        });
+    }
+
+    fn check_len(&mut self, pool: &str, field: &str, items: &[String], expected: u32, banner: &str) {
+        if items.len() != expected as usize {
+            self.report(Severity::Error, &[pool, field], format!(
+                "{}.{} has {} items but the {} banner features {}",
+                pool, field, items.len(), banner, expected,
+            ));
+        }
+    }
+
+    /// Checks the item lists of a pool, those named in `optional` being allowed to be empty
+    fn check_lists(&mut self, pool: &str, featured: &[(&str, &[String])], others: &[(&str, &[String])], optional: &[&str]) {
+        for (field, items) in featured.iter().chain(others) {
+            if items.is_empty() && !optional.contains(field) {
+                self.report(Severity::Error, &[pool, field], format!("{}.{} is empty", pool, field));
+            }
+            for (index, item) in items.iter().enumerate() {
+                if items[..index].contains(item) {
+                    self.report(Severity::Warning, &[pool, field, item, item], format!("{} appears more than once in {}.{}", item, pool, field));
+                }
+            }
+        }
+        for (featured_field, featured_items) in featured {
+            for item in featured_items.iter() {
+                for (other_field, other_items) in others {
+                    if other_items.contains(item) {
+                        self.report(Severity::Error, &[pool, other_field, item], format!(
+                            "{} is featured in {}.{} but also listed in {}.{}",
+                            item, pool, featured_field, pool, other_field,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_probability(&mut self, path: &[&str], probability: f64) {
+        if !(0. ..=1.).contains(&probability) {
+            self.report(Severity::Error, path, format!("{}.{} must be between 0 and 1, found {}", path[0], path[1..].join("."), probability));
+        }
+    }
+
+    fn check_pity(&mut self, pool: &str, field: &str, pity: &PityRules) {
+        self.check_probability(&[pool, field, "base_probability"], pity.base_probability);
+        if pity.max == 0 {
+            self.report(Severity::Error, &[pool, field, "max"], format!("{}.{}.max must be at least 1", pool, field));
+        }
+        match &pity.curve {
+            PityCurve::Linear { slope: rate, .. } | PityCurve::Increment { step: rate, .. } if *rate < 0. => {
+                self.report(Severity::Error, &[pool, field, "curve"], format!("{}.{}.curve must not decrease", pool, field));
+            }
+            PityCurve::Step { steps } => {
+                if steps.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                    self.report(Severity::Error, &[pool, field, "curve", "steps"], format!("{}.{}.steps must be in increasing pull order", pool, field));
+                }
+                for (_, probability) in steps {
+                    self.check_probability(&[pool, field, "curve", "steps"], *probability);
+                }
+            }
+            PityCurve::Table { probabilities } => {
+                if probabilities.is_empty() {
+                    self.report(Severity::Error, &[pool, field, "curve", "probabilities"], format!("{}.{}.probabilities is empty", pool, field));
+                }
+                for probability in probabilities {
+                    self.check_probability(&[pool, field, "curve", "probabilities"], *probability);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_featured(&mut self, pool: &str, field: &str, featured: &FeaturedRules) {
+        self.check_probability(&[pool, field, "probability"], featured.probability);
+        let streaks = featured.radiance.as_ref().map_or(1, |radiance| radiance.probabilities.len().max(1));
+        if !featured.guarantee && (0..streaks as u32).all(|streak| featured.up_probability(streak) <= 0.) {
+            self.report(Severity::Error, &[pool, field], format!("{}.{} can never be won without a guarantee", pool, field));
+        }
+        if let Some(radiance) = &featured.radiance {
+            if radiance.probabilities.is_empty() {
+                self.report(Severity::Error, &[pool, field, "radiance"], format!("{}.{}.radiance.probabilities is empty", pool, field));
+            }
+            for probability in &radiance.probabilities {
+                self.check_probability(&[pool, field, "radiance"], *probability);
+            }
+        }
+    }
+
+    fn check_weights(&mut self, pool: &str, field: &str, items: &ItemList, as_of: Option<&str>) {
+        let mut pickable = false;
+        for (item, weight, available_from) in items.configured() {
+            if let Some(weight) = weight {
+                if !weight.is_finite() || weight < 0. {
+                    self.report(Severity::Error, &[pool, field, item, "weight"], format!("{}.{}: the weight of {} must be a non-negative number", pool, field, item));
+                }
+            }
+            if let Some(from) = available_from {
+                if !from.chars().any(|c| c.is_ascii_digit()) {
+                    self.report(Severity::Error, &[pool, field, item, "available_from"], format!(
+                        "{}.{}: {} must be available from a version or date, found {}", pool, field, item, from,
+                    ));
+                }
+            }
+            let available = match (available_from, as_of) {
+                (Some(from), Some(as_of)) => compare_versions(as_of, from) != Ordering::Less,
+                _ => true,
+            };
+            pickable |= available && weight.unwrap_or(1.) > 0.;
+        }
+        if !items.is_empty() && !pickable {
+            self.report(Severity::Error, &[pool, field], format!("{}.{} has no item that can be picked as of {}", pool, field, as_of.unwrap_or("now")));
+        }
+    }
+
+    fn check_pool(&mut self, name: &str, pool: &BannerPool, rules: &BannerRules, as_of: Option<&str>) {
+        if let Some(featured) = &pool.featured_5star {
+            self.check_featured(name, "featured_5star", featured);
+        }
+        if let Some(pity) = &pool.pity_5star {
+            self.check_pity(name, "pity_5star", pity);
+        }
+        if let Some(pity) = &pool.pity_4star {
+            self.check_pity(name, "pity_4star", pity);
+        }
+        if let Some(spark) = &pool.spark {
+            if spark.threshold == 0 {
+                self.report(Severity::Error, &[name, "spark", "threshold"], format!("{}.spark.threshold must be at least 1", name));
+            }
+            if rules.star5.featured.is_none() {
+                self.report(Severity::Error, &[name, "spark"], format!(
+                    "the {} banner has no featured 5 star to exchange {}.spark for", rules.name, name,
+                ));
+            }
+        }
+        let mut optional = vec!["up_5star_weapon", "other_5star_weapon"];
+        if let Some(featured) = &rules.star5.featured {
+            let up_5star = [pool.up_5star.as_slice(), pool.up_5star_weapon.as_slice()].concat();
+            self.check_len(name, "up_5star", &up_5star, featured.count, &rules.name);
+        } else {
+            optional.push("up_5star");
+        }
+        if let Some(featured) = &rules.star4.featured {
+            self.check_len(name, "up_4star", &pool.up_4star, featured.count, &rules.name);
+        } else {
+            optional.push("up_4star");
+        }
+        // Without balance, off-banner 4 stars are all of the banner item type
+        if rules.star4.balance.is_none() {
+            optional.push(match rules.item_type {
+                ItemType::Character => "other_4star_weapon",
+                ItemType::Weapon => "other_4star_character",
+            });
+        }
+        for script in &rules.script {
+            let Some(featured) = script.featured else {
+                continue;
+            };
+            let (field, count) = match script.level {
+                ItemLevel::Star5 => ("up_5star", pool.up_5star.len() + pool.up_5star_weapon.len()),
+                ItemLevel::Star4 => ("up_4star", pool.up_4star.len()),
+                ItemLevel::Star3 => ("other_3star", 0),
+            };
+            optional.retain(|optional| *optional != field);
+            if featured as usize >= count {
+                self.report(Severity::Error, &[name, field], format!(
+                    "the {} banner scripts item {} of {}.{}, which has {} items",
+                    rules.name, featured, name, field, count,
+                ));
+            }
+        }
+        for (field, items) in pool.off_banner_lists() {
+            self.check_weights(name, field, items, as_of);
+        }
+        self.check_lists(name, &[
+            ("up_5star", &pool.up_5star),
+            ("up_5star_weapon", &pool.up_5star_weapon),
+            ("up_4star", &pool.up_4star),
+        ], &[
+            ("other_5star", &pool.other_5star),
+            ("other_5star_weapon", &pool.other_5star_weapon),
+            ("other_4star_character", &pool.other_4star_character),
+            ("other_4star_weapon", &pool.other_4star_weapon),
+            ("other_3star", &pool.other_3star),
+        ], &optional);
+    }
+}
+
+/// Checks a pool given on its own, such as one sent to the server, against the rules it is pulled under
+pub fn validate_pool(name: &str, pool: &BannerPool, rules: &BannerRules, as_of: Option<&str>) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        file_path: name,
+        source: "",
+        diagnostics: Vec::new(),
+    };
+    validator.check_pool(name, pool, rules, as_of);
+    validator.diagnostics
+}
+
+/// Reads and parses a pool configuration file, then checks it against the
+/// rates in use, those of the `rules` set and as of the `as_of` version or
+/// date when given rather than the ones in the file. The pools are returned
+/// alongside every diagnostic found, unless the file could not be read or
+/// parsed at all.
+pub fn validate_pools(file_path: &str, rules: Option<&str>, as_of: Option<&str>) -> (Option<Pools>, Vec<Diagnostic>) {
+    let diagnostic = |location, message| Diagnostic {
+        severity: Severity::Error,
+        file_path: file_path.to_string(),
+        location,
+        message,
+    };
+    let source = match fs::read_to_string(file_path) {
+        Ok(source) => source,
+        Err(e) => return (None, vec![diagnostic(None, format!("Unable to read file: {}", e))]),
+    };
+    let mut pools: Pools = match serde_json::from_str(&source) {
+        Ok(pools) => pools,
+        Err(e) => {
+            let location = if e.line() > 0 { Some((e.line(), e.column())) } else { None };
+            let message = e.to_string();
+            // serde_json appends the location to its message, which is already in the prefix
+            let message = message.split(" at line ").next().unwrap_or(&message).to_string();
+            return (None, vec![diagnostic(location, message)]);
+        }
+    };
+
+    if let Some(rules) = rules {
+        pools.rules = Some(rules.to_string());
+    }
+    if let Some(as_of) = as_of {
+        pools.as_of = Some(as_of.to_string());
+    }
+
+    let mut validator = Validator {
+        file_path,
+        source: &source,
+        diagnostics: Vec::new(),
+    };
+    if let Some(name) = &pools.rules {
+        if RuleSet::named(name).is_none() {
+            validator.report(Severity::Error, &["rules"], format!("unknown rule set {}, expected one of {}", name, RULE_SETS.join(", ")));
+            return (Some(pools), validator.diagnostics);
+        }
+    }
+    let as_of = pools.as_of.as_deref();
+    validator.check_pool("character1", &pools.character1, &pools.character_rules(), as_of);
+    validator.check_pool("character2", &pools.character2, &pools.character2.rules(pools.rule_set().character), as_of);
+    validator.check_pool("weapon", &pools.weapon, &pools.weapon_rules(), as_of);
+    match (&pools.chronicled, pools.chronicled_rules()) {
+        (Some(pool), Some(rules)) => validator.check_pool("chronicled", pool, &rules, as_of),
+        (Some(_), None) => validator.report(Severity::Warning, &["chronicled"], format!(
+            "chronicled is ignored, the {} rule set has no Chronicled Wish", pools.rule_set().name,
+        )),
+        (None, _) => {}
+    }
+    match (&pools.beginner, pools.beginner_rules()) {
+        (Some(pool), Some(rules)) => validator.check_pool("beginner", pool, &rules, as_of),
+        (Some(_), None) => validator.report(Severity::Warning, &["beginner"], format!(
+            "beginner is ignored, the {} rule set has no Beginners' Wish", pools.rule_set().name,
+        )),
+        (None, _) => {}
+    }
+    if pools.character2.rules(pools.rule_set().character) != pools.character_rules() {
+        validator.report(Severity::Warning, &["character2"], String::from(
+            "character2 overrides rules differently from character1, whose rules the shared character banner uses in interactive mode",
+        ));
+    }
+    (Some(pools), validator.diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Validates `source` as a pool configuration file
+    fn validate(name: &str, source: &str) -> Vec<Diagnostic> {
+        let file_path = std::env::temp_dir().join(format!("validation-{}-{}.json", std::process::id(), name));
+        fs::write(&file_path, source).expect("Unable to write the pool configuration");
+        let (_, diagnostics) = validate_pools(file_path.to_str().expect("Non UTF-8 path"), None, None);
+        fs::remove_file(&file_path).expect("Unable to remove the pool configuration");
+        diagnostics
+    }
+
+    /// Line of each diagnostic in `source`
+    fn line<'a>(source: &'a str, diagnostic: &Diagnostic) -> &'a str {
+        let (line, _) = diagnostic.location.unwrap_or_else(|| panic!("{} is not located", diagnostic));
+        source.lines().nth(line - 1).expect("Location past the end")
+    }
+
+    #[test]
+    fn each_rejection_is_reported_at_its_field() {
+        let pools: Value = serde_json::from_str(include_str!("../../pool.json")).expect("Invalid pool.json");
+        let merged = |mut base: Value, extra: Value| {
+            if let (Some(base), Value::Object(extra)) = (base.as_object_mut(), extra) {
+                base.extend(extra);
+            }
+            base
+        };
+        let pity = |extra| merged(json!({ "base_probability": 0.006, "max": 90, "curve": { "model": "linear", "threshold": 73, "slope": 10. } }), extra);
+        let featured = |extra| merged(json!({ "probability": 0.5, "count": 1 }), extra);
+        let cases = [
+            ("character1", "up_4star", json!(["香菱"]), "character1.up_4star has 1 items but the character banner features 3", "\"up_4star\""),
+            ("character1", "other_5star", json!([]), "character1.other_5star is empty", "\"other_5star\""),
+            ("character1", "other_5star", json!(["阿贝多", "刻晴"]), "阿贝多 is featured in character1.up_5star but also listed in character1.other_5star", "\"阿贝多\""),
+            ("weapon", "pity_5star", pity(json!({ "base_probability": 1.5 })), "weapon.pity_5star.base_probability must be between 0 and 1", "\"base_probability\""),
+            ("weapon", "pity_5star", pity(json!({ "max": 0 })), "weapon.pity_5star.max must be at least 1", "\"max\""),
+            ("weapon", "pity_4star", pity(json!({ "curve": { "model": "increment", "threshold": 8, "step": -0.1 } })), "weapon.pity_4star.curve must not decrease", "\"curve\""),
+            ("weapon", "pity_4star", pity(json!({ "curve": { "model": "step", "steps": [[9, 0.5], [8, 1.]] } })), "weapon.pity_4star.steps must be in increasing pull order", "\"steps\""),
+            ("weapon", "pity_4star", pity(json!({ "curve": { "model": "table", "probabilities": [] } })), "weapon.pity_4star.probabilities is empty", "\"probabilities\""),
+            ("character2", "featured_5star", featured(json!({ "probability": 2. })), "character2.featured_5star.probability must be between 0 and 1", "\"probability\""),
+            ("character2", "featured_5star", featured(json!({ "radiance": { "probabilities": [] } })), "character2.featured_5star.radiance.probabilities is empty", "\"radiance\""),
+            ("character2", "featured_5star", featured(json!({ "probability": 0., "guarantee": false })), "character2.featured_5star can never be won without a guarantee", "\"featured_5star\""),
+            ("character1", "other_5star", json!([{ "weight": -1, "name": "刻晴" }]), "the weight of 刻晴 must be a non-negative number", "\"weight\""),
+            ("character1", "other_5star", json!([{ "name": "刻晴", "available_from": "soon" }]), "刻晴 must be available from a version or date, found soon", "\"available_from\""),
+            ("character1", "other_5star", json!([{ "name": "刻晴", "weight": 0 }]), "character1.other_5star has no item that can be picked as of now", "\"other_5star\""),
+            ("weapon", "spark", json!({ "threshold": 0 }), "weapon.spark.threshold must be at least 1", "\"threshold\""),
+            ("beginner", "spark", json!({ "threshold": 10 }), "the beginner banner has no featured 5 star to exchange beginner.spark for", "\"spark\""),
+            ("beginner", "up_4star", json!([]), "the beginner banner scripts item 0 of beginner.up_4star, which has 0 items", "\"up_4star\""),
+        ];
+        for (index, (pool, field, value, message, located)) in cases.into_iter().enumerate() {
+            let mut pools = pools.clone();
+            pools[pool][field] = value;
+            let source = serde_json::to_string_pretty(&pools).expect("Unable to serialize pools");
+            let diagnostics = validate(&format!("case{}", index), &source);
+            let diagnostic = diagnostics.iter()
+                .find(|diagnostic| diagnostic.message.contains(message))
+                .unwrap_or_else(|| panic!("No \"{}\" among {:?}", message, diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>()));
+            assert!(diagnostic.severity == Severity::Error, "{}", diagnostic);
+            // Pools are serialized in alphabetical order, each ending at the first line closing an object at its depth
+            let (line_number, _) = diagnostic.location.expect("Not located");
+            let lines: Vec<&str> = source.lines().collect();
+            let start = lines.iter().position(|l| *l == format!("  \"{}\": {{", pool)).expect("No such pool");
+            let end = start + lines[start..].iter().position(|l| l.starts_with("  }")).expect("Pool not closed");
+            assert!((start + 1..=end + 1).contains(&line_number), "{} is outside the {} pool", diagnostic, pool);
+            assert!(line(&source, diagnostic).contains(located), "{} points at {}", diagnostic, line(&source, diagnostic));
+        }
+    }
+
+    #[test]
+    fn unknown_rule_sets_are_rejected() {
+        let source = include_str!("../../pool.json").replacen('{', "{\n    \"rules\": \"genshin-9.9\",", 1);
+        let diagnostics = validate("rules", &source);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("unknown rule set genshin-9.9"));
+        assert_eq!(diagnostics[0].location, Some((2, 5)));
+    }
+
+    #[test]
+    fn duplicates_are_located_at_their_second_occurrence() {
+        // 香菱 appears all over the file before the weapon pool, which lists it last and now after 柯莱 too
+        let source = include_str!("../../pool.json").replacen(
+            "\"other_4star_character\": [\"多莉\", \"柯莱\"",
+            "\"other_4star_character\": [\"多莉\", \"柯莱\", \"香菱\"",
+            3,
+        );
+        let diagnostics = validate("duplicates", &source);
+        let duplicate: Vec<_> = diagnostics.iter().filter(|d| d.message.contains("香菱 appears more than once")).collect();
+        assert_eq!(duplicate.len(), 1, "{:?}", diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>());
+        assert!(duplicate[0].severity == Severity::Warning);
+        assert_eq!(duplicate[0].message, "香菱 appears more than once in weapon.other_4star_character");
+        let (line, column) = duplicate[0].location.expect("Not located");
+        let weapon_line = source.lines().position(|l| l.contains("\"weapon\": {")).expect("No weapon pool") + 1;
+        let text = source.lines().nth(line - 1).expect("Location past the end");
+        assert!(line > weapon_line && text.contains("other_4star_character"));
+        // The last 香菱 on the line, past the one inserted after 柯莱
+        let inserted = text.find("\"柯莱\", \"香菱\"").expect("No inserted item") + "\"柯莱\", ".len();
+        assert!(text[..inserted].chars().count() < column - 1);
+        assert_eq!(&text[text.char_indices().nth(column - 1).expect("Column past the end").0..][..8], "\"香菱\"");
+    }
+}
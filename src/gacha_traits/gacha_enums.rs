@@ -1,9 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ItemLevel {
     Star3,
     Star4,
     Star5,
 }
 
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ItemType {
     Character,
     Weapon,
@@ -14,33 +20,17 @@ pub enum UpType {
     NonUp
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum WantState {
     Want(u32, u32),
     None
 }
 
-pub enum NormalGachaType {
-    Character5Star,
-    Weapon5Star,
-    Character4Star,
-    Weapon4Star,
-    Other3Star,
-}
-
-pub enum CharacterGachaType {
-    Up5Star,
-    Up4Star(u32),
-    Other5Star,
-    Other4StarCharacter,
-    Other4StarWeapon,
-    Other3Star,
-}
-
-pub enum WeaponGachaType {
+#[allow(clippy::enum_variant_names)]
+pub enum GachaResult {
     Up5Star(u32),
     Up4Star(u32),
-    Other5Star,
-    Other4StarCharacter,
-    Other4StarWeapon,
+    Other5Star(ItemType),
+    Other4Star(ItemType),
     Other3Star,
 }
@@ -1,227 +1,517 @@
 pub mod gacha_enums;
+pub mod rule_sets;
 
+use crate::common::rng;
 use gacha_enums::*;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-pub trait Gacha5StarInfo {
-    const BASE_PROBABILITY_5STAR: f64;
-    const THRESHOLD_5STAR: u32;
-    const MAX_5STAR: u32;
-
-    fn get_since_last_5star(&self) -> u32;
-
-    fn set_since_last_5star(&mut self, count: u32);
+/// Shape of the probability of an item tier as the pulls since the last one grow
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum PityCurve {
+    /// Ramps up by `slope` times the base probability per pull after `threshold`
+    Linear { threshold: u32, slope: f64 },
+    /// Ramps up by a fixed `step` per pull after `threshold`
+    Increment { threshold: u32, step: f64 },
+    /// Fixed probability from each listed pull onwards, as `[pull, probability]` pairs
+    Step { steps: Vec<(u32, f64)> },
+    /// Probability of each pull, starting from the first, the last one repeating
+    Table { probabilities: Vec<f64> },
+}
 
-    fn prob_5star(count: u32) -> f64 {
-        if count > Self::THRESHOLD_5STAR {
-            Self::BASE_PROBABILITY_5STAR * (1. + 10. * (count - Self::THRESHOLD_5STAR) as f64)
-        } else {
-            Self::BASE_PROBABILITY_5STAR
+impl PityCurve {
+    fn probability(&self, base_probability: f64, count: u32) -> f64 {
+        match self {
+            Self::Linear { threshold, slope } if count > *threshold => {
+                base_probability * (1. + slope * (count - threshold) as f64)
+            }
+            Self::Increment { threshold, step } if count > *threshold => {
+                base_probability + step * (count - threshold) as f64
+            }
+            Self::Step { steps } => steps.iter()
+                .take_while(|(from, _)| *from <= count)
+                .last()
+                .map_or(base_probability, |(_, probability)| *probability),
+            Self::Table { probabilities } => probabilities.get((count as usize).saturating_sub(1))
+                .or(probabilities.last())
+                .copied()
+                .unwrap_or(base_probability),
+            _ => base_probability,
         }
     }
 }
 
-pub trait Gacha4StarInfo {
-    const BASE_PROBABILITY_4STAR: f64;
-    const THRESHOLD_4STAR: u32;
-    const MAX_4STAR: u32;
-
-    fn get_since_last_4star(&self) -> u32;
+/// Probability of an item tier as a function of the pulls since the last one
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct PityRules {
+    pub base_probability: f64,
+    pub curve: PityCurve,
+    /// Pull at which the tier is guaranteed (hard pity)
+    pub max: u32,
+}
 
-    fn set_since_last_4star(&mut self, count: u32);
+impl PityRules {
+    pub fn linear(base_probability: f64, threshold: u32, max: u32) -> PityRules {
+        PityRules {
+            base_probability,
+            curve: PityCurve::Linear { threshold, slope: 10. },
+            max,
+        }
+    }
 
-    fn prob_4star(count: u32) -> f64 {
-        if count > Self::THRESHOLD_4STAR {
-            Self::BASE_PROBABILITY_4STAR * (1. + 10. * (count - Self::THRESHOLD_4STAR) as f64)
+    pub fn probability(&self, count: u32) -> f64 {
+        if count >= self.max {
+            1.
         } else {
-            Self::BASE_PROBABILITY_4STAR
+            self.curve.probability(self.base_probability, count)
         }
     }
+
+    /// First pull at which the probability rises above the base one (soft pity)
+    pub fn soft_pity(&self) -> u32 {
+        (1..self.max)
+            .find(|&count| self.probability(count) > self.base_probability)
+            .unwrap_or(self.max)
+    }
 }
 
-pub trait Up5Star {
-    const UP_PROBABILITY_5STAR: f64;
-    const UP_5STAR_NUM: u32;
+/// Capturing Radiance: the featured probability of a roll rises with the rolls lost in a row
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RadianceRules {
+    /// Featured probability by the number of rolls lost in a row, the last one repeating
+    #[serde(default = "RadianceRules::default_probabilities")]
+    pub probabilities: Vec<f64>,
+}
 
-    fn get_last_5star_is_up(&self) -> bool;
+impl RadianceRules {
+    pub fn default_probabilities() -> Vec<f64> {
+        vec![0.5, 0.5, 0.75, 1.]
+    }
+}
 
-    fn set_last_5star_is_up(&mut self, is_up: bool);
+/// Featured items of a tier and the guarantee after losing the featured roll
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeaturedRules {
+    pub probability: f64,
+    pub count: u32,
+    /// Whether losing the featured roll guarantees the next one
+    #[serde(default = "FeaturedRules::default_guarantee")]
+    pub guarantee: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radiance: Option<RadianceRules>,
+}
 
-    fn get_5star_up_type(&mut self) -> UpType {
-        if self.get_last_5star_is_up() {
-            if rand::random::<f64>() < Self::UP_PROBABILITY_5STAR {
-                self.set_last_5star_is_up(true);
-                if Self::UP_5STAR_NUM == 1 {
-                    UpType::Up(0)
-                } else {
-                    UpType::Up(rand::thread_rng().gen_range(0..Self::UP_5STAR_NUM))
-                }
-            } else {
-                self.set_last_5star_is_up(false);
-                UpType::NonUp
-            }
+impl FeaturedRules {
+    fn default_guarantee() -> bool {
+        true
+    }
+
+    fn pick(&self) -> UpType {
+        if self.count == 1 {
+            UpType::Up(0)
         } else {
-            self.set_last_5star_is_up(true);
-            if Self::UP_5STAR_NUM == 1 {
-                UpType::Up(0)
-            } else {
-                UpType::Up(rand::thread_rng().gen_range(0..Self::UP_5STAR_NUM))
-            }
+            UpType::Up(rng::gen_range(0..self.count))
         }
     }
-}
 
-pub trait Up4Star {
-    const UP_PROBABILITY_4STAR: f64;
-    const UP_4STAR_NUM: u32;
-
-    fn get_last_4star_is_up(&self) -> bool;
-
-    fn set_last_4star_is_up(&mut self, is_up: bool);
+    /// Probability of winning the featured roll after losing `lost_streak` of them in a row
+    pub fn up_probability(&self, lost_streak: u32) -> f64 {
+        match &self.radiance {
+            Some(radiance) => radiance.probabilities.get(lost_streak as usize)
+                .or(radiance.probabilities.last())
+                .copied()
+                .unwrap_or(self.probability),
+            None => self.probability,
+        }
+    }
 
-    fn get_4star_up_type(&mut self) -> UpType {
-        if self.get_last_4star_is_up() {
-            if rand::random::<f64>() < Self::UP_PROBABILITY_4STAR {
-                self.set_last_4star_is_up(true);
-                if Self::UP_4STAR_NUM == 1 {
-                    UpType::Up(0)
-                } else {
-                    UpType::Up(rand::thread_rng().gen_range(0..Self::UP_4STAR_NUM))
-                }
+    /// Rolls for a featured item, which is guaranteed if the last one was not featured,
+    /// returning the draw it was decided by unless guaranteed
+    pub fn roll(&self, last_is_up: &mut bool, lost_streak: &mut u32) -> (UpType, Option<f64>) {
+        let up_probability = self.up_probability(*lost_streak);
+        let (roll, won) = if *last_is_up {
+            let roll = rng::random::<f64>();
+            if roll < up_probability {
+                *last_is_up = true;
+                *lost_streak = 0;
+                (Some(roll), true)
             } else {
-                self.set_last_4star_is_up(false);
-                UpType::NonUp
+                *last_is_up = !self.guarantee;
+                *lost_streak += 1;
+                (Some(roll), false)
             }
         } else {
-            self.set_last_4star_is_up(true);
-            if Self::UP_4STAR_NUM == 1 {
-                UpType::Up(0)
-            } else {
-                UpType::Up(rand::thread_rng().gen_range(0..Self::UP_4STAR_NUM))
-            }
-        }
+            *last_is_up = true;
+            (None, true)
+        };
+        (if won { self.pick() } else { UpType::NonUp }, roll)
     }
 }
 
-pub trait Want5Star: Gacha5StarInfo {
-    const WANT_5STAR_MAX: u32;
+/// Keeps characters and weapons of a tier from going too long without each other
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceRules {
+    pub threshold: u32,
 }
 
-pub trait Balance5Star: Gacha5StarInfo {
-    const BALANCE_THRESHOLD_5STAR: u32;
-
-    fn get_since_last_5star_character(&self) -> u32;
-
-    fn get_since_last_5star_weapon(&self) -> u32;
-
-    fn set_since_last_5star_character(&mut self, count: u32);
-
-    fn set_since_last_5star_weapon(&mut self, count: u32);
-
-    fn balance_prob_5star(count: u32) -> f64 {
-        if count >  Self::BALANCE_THRESHOLD_5STAR {
-            Self::BASE_PROBABILITY_5STAR * 0.5 * (1. + 10. * (count - Self::BALANCE_THRESHOLD_5STAR) as f64)
+impl BalanceRules {
+    /// Weight of a type at `count` pulls since its last item, including the pull being rolled
+    pub fn probability(&self, base_probability: f64, count: u32) -> f64 {
+        if count > self.threshold {
+            base_probability * 0.5 * (1. + 10. * (count - self.threshold) as f64)
         } else {
-            Self::BASE_PROBABILITY_5STAR * 0.5
+            base_probability * 0.5
         }
     }
 
-    fn get_5star_balance_type(&mut self) -> ItemType {
-        let count_character = self.get_since_last_5star_character() + 1;
-        let count_weapon = self.get_since_last_5star_weapon() + 1;
-        let p_character = Self::balance_prob_5star(count_character);
-        let p_weapon = Self::balance_prob_5star(count_weapon);
+    /// Chance `roll` gives a character at the given counts, including the pull being rolled
+    pub fn character_probability(&self, base_probability: f64, count_character: u32, count_weapon: u32) -> f64 {
+        let p_character = self.probability(base_probability, count_character);
+        let p_weapon = self.probability(base_probability, count_weapon);
+        let total = (p_character + p_weapon).min(1.);
+        match p_character.partial_cmp(&p_weapon) {
+            Some(Ordering::Equal) => 0.5,
+            Some(Ordering::Less) => 1. - (p_weapon / total).min(1.),
+            Some(Ordering::Greater) => (p_character / total).min(1.),
+            None => unreachable!()
+        }
+    }
 
-        let get_character = match p_character.partial_cmp(&p_weapon) {
-            Some(Ordering::Equal) => rand::random::<bool>(),
-            Some(Ordering::Less) => rand::random::<f64>() * (p_character + p_weapon).min(1.) >= p_weapon,
-            Some(Ordering::Greater) => rand::random::<f64>() * (p_character + p_weapon).min(1.) < p_character,
+    /// Rolls for the type of an item, returning the draw it was decided by unless a coin was flipped
+    pub fn roll(&self, base_probability: f64, since_last_character: &mut u32, since_last_weapon: &mut u32) -> (ItemType, Option<f64>) {
+        let count_character = *since_last_character + 1;
+        let count_weapon = *since_last_weapon + 1;
+        let p_character = self.probability(base_probability, count_character);
+        let p_weapon = self.probability(base_probability, count_weapon);
+
+        let (roll, get_character) = match p_character.partial_cmp(&p_weapon) {
+            Some(Ordering::Equal) => (None, rng::random::<bool>()),
+            Some(Ordering::Less) => {
+                let roll = rng::random::<f64>() * (p_character + p_weapon).min(1.);
+                (Some(roll), roll >= p_weapon)
+            }
+            Some(Ordering::Greater) => {
+                let roll = rng::random::<f64>() * (p_character + p_weapon).min(1.);
+                (Some(roll), roll < p_character)
+            }
             None => unreachable!()
         };
 
         match get_character {
             true => {
-                self.set_since_last_5star_character(0);
-                self.set_since_last_5star_weapon(count_weapon);
-                ItemType::Character
+                *since_last_character = 0;
+                *since_last_weapon = count_weapon;
+                (ItemType::Character, roll)
             }
             false => {
-                self.set_since_last_5star_character(count_character);
-                self.set_since_last_5star_weapon(0);
-                ItemType::Weapon
+                *since_last_character = count_character;
+                *since_last_weapon = 0;
+                (ItemType::Weapon, roll)
             }
         }
     }
 }
 
-pub trait Balance4Star: Gacha4StarInfo {
-    const BALANCE_THRESHOLD_4STAR: u32;
+/// Epitomized Path: fate points earned on missing the charted featured item
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathRules {
+    pub max_fate_points: u32,
+    /// Chronicled Wish course: winning the featured roll always yields the charted item
+    #[serde(default)]
+    pub course: bool,
+}
+
+/// Discounted multi-pulls, such as the 10-pull costing 8 fates on the Beginners' Wish
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricingRules {
+    pub multi_size: u32,
+    pub multi_cost: u32,
+}
+
+/// An item of a tier given at pull `by` at the latest, the first one of the tier
+/// being forced to a featured item when `featured` is set
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedRules {
+    pub level: ItemLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub featured: Option<u32>,
+    pub by: u32,
+    /// Whether an item of a higher tier also counts as the scripted one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub or_higher: bool,
+}
 
-    fn get_since_last_4star_character(&self) -> u32;
+/// When spark points are exchanged
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SparkPolicy {
+    /// For the charted or first featured 5 star as soon as the threshold is reached
+    Automatic,
+    /// By the player, simulations exchanging only when a goal is still missed
+    #[default]
+    Manual,
+}
 
-    fn get_since_last_4star_weapon(&self) -> u32;
+/// Exchange of the pulls made on a banner for a featured 5 star of choice
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparkRules {
+    /// Spark points, one per pull, needed for an exchange
+    pub threshold: u32,
+    /// Whether points left unexchanged carry over to the next banner of the same kind
+    #[serde(default)]
+    pub carry_over: bool,
+    #[serde(default)]
+    pub policy: SparkPolicy,
+}
 
-    fn set_since_last_4star_character(&mut self, count: u32);
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TierRules {
+    pub pity: PityRules,
+    pub featured: Option<FeaturedRules>,
+    pub balance: Option<BalanceRules>,
+}
 
-    fn set_since_last_4star_weapon(&mut self, count: u32);
+/// Everything that distinguishes one banner from another
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct BannerRules {
+    pub name: String,
+    /// Type of the featured items, and of off-banner items in tiers without balance
+    pub item_type: ItemType,
+    pub star5: TierRules,
+    pub star4: TierRules,
+    pub path: Option<PathRules>,
+    /// Number of pulls the banner allows in total
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<PricingRules>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub script: Vec<ScriptedRules>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spark: Option<SparkRules>,
+}
 
-    fn balance_prob_4star(count: u32) -> f64 {
-        if count >  Self::BALANCE_THRESHOLD_4STAR {
-            Self::BASE_PROBABILITY_4STAR * 0.5 * (1. + 10. * (count - Self::BALANCE_THRESHOLD_4STAR) as f64)
-        } else {
-            Self::BASE_PROBABILITY_4STAR * 0.5
+impl BannerRules {
+    /// Fates spent on `pulls` pulls made at once, as multi-pulls where possible
+    pub fn cost(&self, pulls: u32) -> u32 {
+        match &self.pricing {
+            Some(pricing) => pulls / pricing.multi_size * pricing.multi_cost + pulls % pricing.multi_size,
+            None => pulls,
         }
     }
 
-    fn get_4star_balance_type(&mut self) -> ItemType {
-        let count_character = self.get_since_last_4star_character() + 1;
-        let count_weapon = self.get_since_last_4star_weapon() + 1;
-        let p_character = Self::balance_prob_4star(count_character);
-        let p_weapon = Self::balance_prob_4star(count_weapon);
+    pub fn character() -> BannerRules {
+        BannerRules {
+            name: String::from("character"),
+            item_type: ItemType::Character,
+            star5: TierRules {
+                pity: PityRules::linear(0.006, 73, 90),
+                featured: Some(FeaturedRules { probability: 0.5, count: 1, guarantee: true, radiance: None }),
+                balance: None,
+            },
+            star4: TierRules {
+                pity: PityRules::linear(0.051, 8, 10),
+                featured: Some(FeaturedRules { probability: 0.5, count: 3, guarantee: true, radiance: None }),
+                balance: Some(BalanceRules { threshold: 17 }),
+            },
+            path: None,
+            limit: None,
+            pricing: None,
+            script: Vec::new(),
+            spark: None,
+        }
+    }
 
-        let get_character = match p_character.partial_cmp(&p_weapon) {
-            Some(Ordering::Equal) => rand::random::<bool>(),
-            Some(Ordering::Less) => rand::random::<f64>() * (p_character + p_weapon).min(1.) >= p_weapon,
-            Some(Ordering::Greater) => rand::random::<f64>() * (p_character + p_weapon).min(1.) < p_character,
-            None => unreachable!()
-        };
+    pub fn weapon() -> BannerRules {
+        BannerRules {
+            name: String::from("weapon"),
+            item_type: ItemType::Weapon,
+            star5: TierRules {
+                pity: PityRules::linear(0.007, 62, 80),
+                featured: Some(FeaturedRules { probability: 0.75, count: 2, guarantee: true, radiance: None }),
+                balance: None,
+            },
+            star4: TierRules {
+                pity: PityRules::linear(0.06, 7, 10),
+                featured: Some(FeaturedRules { probability: 0.75, count: 5, guarantee: true, radiance: None }),
+                balance: Some(BalanceRules { threshold: 14 }),
+            },
+            path: Some(PathRules { max_fate_points: 2, course: false }),
+            limit: None,
+            pricing: None,
+            script: Vec::new(),
+            spark: None,
+        }
+    }
 
-        match get_character {
-            true => {
-                self.set_since_last_4star_character(0);
-                self.set_since_last_4star_weapon(count_weapon);
-                ItemType::Character
-            }
-            false => {
-                self.set_since_last_4star_character(count_character);
-                self.set_since_last_4star_weapon(0);
-                ItemType::Weapon
-            }
+    /// Chronicled Wish: characters and weapons featured together, one of them charted as the course
+    pub fn chronicled() -> BannerRules {
+        BannerRules {
+            name: String::from("chronicled"),
+            item_type: ItemType::Character,
+            star5: TierRules {
+                pity: PityRules::linear(0.006, 73, 90),
+                featured: Some(FeaturedRules { probability: 0.5, count: 4, guarantee: true, radiance: None }),
+                balance: Some(BalanceRules { threshold: 146 }),
+            },
+            star4: TierRules {
+                pity: PityRules::linear(0.051, 8, 10),
+                featured: None,
+                balance: Some(BalanceRules { threshold: 17 }),
+            },
+            path: Some(PathRules { max_fate_points: 1, course: true }),
+            limit: None,
+            pricing: None,
+            script: Vec::new(),
+            spark: None,
+        }
+    }
+
+    /// Beginners' Wish: 20 discounted pulls, the first 4 star being the featured character
+    pub fn beginner() -> BannerRules {
+        BannerRules {
+            name: String::from("beginner"),
+            item_type: ItemType::Character,
+            star5: TierRules {
+                pity: PityRules::linear(0.006, 73, 90),
+                featured: None,
+                balance: None,
+            },
+            star4: TierRules {
+                pity: PityRules::linear(0.051, 8, 10),
+                featured: None,
+                balance: Some(BalanceRules { threshold: 17 }),
+            },
+            path: None,
+            limit: Some(20),
+            pricing: Some(PricingRules { multi_size: 10, multi_cost: 8 }),
+            script: vec![ScriptedRules { level: ItemLevel::Star4, featured: Some(0), by: 10, or_higher: false }],
+            spark: None,
+        }
+    }
+
+    pub fn standard() -> BannerRules {
+        BannerRules {
+            name: String::from("standard"),
+            item_type: ItemType::Character,
+            star5: TierRules {
+                pity: PityRules::linear(0.006, 73, 90),
+                featured: None,
+                balance: Some(BalanceRules { threshold: 146 }),
+            },
+            star4: TierRules {
+                pity: PityRules::linear(0.051, 8, 10),
+                featured: None,
+                balance: Some(BalanceRules { threshold: 17 }),
+            },
+            path: None,
+            limit: None,
+            pricing: None,
+            script: Vec::new(),
+            spark: None,
         }
     }
 }
 
-pub trait GeneralGachaMechanism: Gacha5StarInfo + Gacha4StarInfo {
-    fn get_item_level(&mut self) -> ItemLevel {
-        let count_5star = self.get_since_last_5star() + 1;
-        let count_4star = self.get_since_last_4star() + 1;
-        let p_5star = Self::prob_5star(count_5star);
-        let p_4star = Self::prob_4star(count_4star);
+/// Pity counters and guarantees of a banner, independent of its rules
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GachaState {
+    /// Pulls made on the banner in total
+    pub pulls: u32,
+    pub since_last_5star: u32,
+    pub since_last_4star: u32,
+    pub since_last_5star_character: u32,
+    pub since_last_5star_weapon: u32,
+    pub since_last_4star_character: u32,
+    pub since_last_4star_weapon: u32,
+    pub last_5star_is_up: bool,
+    pub last_4star_is_up: bool,
+    /// Featured 5 star rolls lost in a row, for Capturing Radiance
+    pub lost_5star_streak: u32,
+    pub lost_4star_streak: u32,
+    pub want_5star_state: WantState,
+    /// Pulls counted towards the spark and not exchanged yet
+    pub spark_points: u32,
+}
 
-        let rnd_num: f64 = rand::random();
-        if rnd_num < p_5star {
-            self.set_since_last_5star(0);
-            self.set_since_last_4star(count_4star);
-            ItemLevel::Star5
-        } else if rnd_num < p_5star + p_4star {
-            self.set_since_last_5star(count_5star);
-            self.set_since_last_4star(0);
-            ItemLevel::Star4
-        } else {
-            self.set_since_last_5star(count_5star);
-            self.set_since_last_4star(count_4star);
-            ItemLevel::Star3
+impl GachaState {
+    pub fn new() -> GachaState {
+        GachaState {
+            pulls: 0,
+            since_last_5star: 0,
+            since_last_4star: 0,
+            since_last_5star_character: 0,
+            since_last_5star_weapon: 0,
+            since_last_4star_character: 0,
+            since_last_4star_weapon: 0,
+            last_5star_is_up: true,
+            last_4star_is_up: true,
+            lost_5star_streak: 0,
+            lost_4star_streak: 0,
+            want_5star_state: WantState::None,
+            spark_points: 0,
+        }
+    }
+}
+
+impl Default for GachaState {
+    fn default() -> GachaState {
+        GachaState::new()
+    }
+}
+
+/// A banner that can be pulled on without knowing its concrete type, so that
+/// banners with different rules can be held together as `Box<dyn Banner>`
+pub trait Banner {
+    fn pull(&mut self) -> GachaResult;
+
+    /// Pulls `n` times, or as many times as the limit of the banner still allows,
+    /// automatic spark exchanges following the pulls that reach them
+    fn pull_n(&mut self, n: u32) -> Vec<GachaResult> {
+        let n = self.remaining().map_or(n, |remaining| remaining.min(n));
+        let mut results = Vec::new();
+        for _ in 0..n {
+            results.push(self.pull());
+            results.extend(self.auto_exchange());
+        }
+        results
+    }
+
+    fn state(&self) -> &GachaState;
+
+    /// Pulls left on a banner with a limit, which callers check before pulling
+    fn remaining(&self) -> Option<u32> {
+        self.rules().limit.map(|limit| limit.saturating_sub(self.state().pulls))
+    }
+
+    /// Exchanges the spark for the featured 5 star `index`, if the banner has
+    /// a spark and enough pulls were made on it
+    fn exchange(&mut self, index: u32) -> Option<GachaResult>;
+
+    /// Featured 5 star the spark goes to when exchanged automatically: the charted one, or the first
+    fn spark_target(&self) -> u32 {
+        match self.state().want_5star_state {
+            WantState::Want(index, _) => index,
+            WantState::None => 0,
         }
     }
+
+    /// Exchanges the spark for `spark_target` if the spark policy is automatic and its threshold is reached
+    fn auto_exchange(&mut self) -> Option<GachaResult> {
+        match self.rules().spark.as_ref()?.policy {
+            SparkPolicy::Automatic => self.exchange(self.spark_target()),
+            SparkPolicy::Manual => None,
+        }
+    }
+
+    /// Moves on to the next banner of the same kind, pity carrying over, and the
+    /// spark points only if the spark says so
+    fn next_banner(&mut self);
+
+    /// Forgets every pull made, keeping the charted path if any
+    fn reset(&mut self);
+
+    fn rules(&self) -> &BannerRules;
 }
@@ -0,0 +1,163 @@
+use super::*;
+
+/// Rule set used when neither the command line nor the pool configuration names one
+pub const DEFAULT_RULE_SET: &str = "genshin-2.0+";
+
+pub const RULE_SETS: [&str; 5] = ["genshin-1.x", "genshin-2.0+", "genshin-5.0+", "star-rail", "arknights"];
+
+/// Rules of every banner as they applied over a range of game versions
+pub struct RuleSet {
+    pub name: &'static str,
+    pub character: BannerRules,
+    pub weapon: BannerRules,
+    /// Chronicled Wish, in games and versions that have one
+    pub chronicled: Option<BannerRules>,
+    /// Beginners' Wish, in games that have one
+    pub beginner: Option<BannerRules>,
+    pub standard: BannerRules,
+}
+
+impl RuleSet {
+    pub fn named(name: &str) -> Option<RuleSet> {
+        let current = RuleSet {
+            name: "genshin-2.0+",
+            character: BannerRules::character(),
+            weapon: BannerRules::weapon(),
+            chronicled: Some(BannerRules::chronicled()),
+            beginner: Some(BannerRules::beginner()),
+            standard: BannerRules::standard(),
+        };
+        match name {
+            // Before the Epitomized Path, neither featured weapon could be charted
+            "genshin-1.x" => Some(RuleSet {
+                name: "genshin-1.x",
+                weapon: BannerRules { path: None, ..current.weapon },
+                ..current
+            }),
+            "genshin-2.0+" => Some(current),
+            // Capturing Radiance on character banners, a single fate point on the Epitomized Path
+            "genshin-5.0+" => {
+                let mut character = current.character;
+                if let Some(featured) = &mut character.star5.featured {
+                    featured.radiance = Some(RadianceRules { probabilities: RadianceRules::default_probabilities() });
+                }
+                let mut weapon = current.weapon;
+                if let Some(path) = &mut weapon.path {
+                    path.max_fate_points = 1;
+                }
+                Some(RuleSet { name: "genshin-5.0+", character, weapon, ..current })
+            }
+            "star-rail" => Some(RuleSet::star_rail()),
+            "arknights" => Some(RuleSet::arknights()),
+            _ => None,
+        }
+    }
+}
+
+impl RuleSet {
+    /// Honkai: Star Rail warps, light cones taking the place of weapons
+    fn star_rail() -> RuleSet {
+        let tier = |base_probability, threshold, max, featured: Option<(f64, u32)>| TierRules {
+            pity: PityRules::linear(base_probability, threshold, max),
+            featured: featured.map(|(probability, count)| FeaturedRules { probability, count, guarantee: true, radiance: None }),
+            balance: None,
+        };
+        let banner = |name: &str, item_type, star5, star4| BannerRules {
+            name: String::from(name),
+            item_type,
+            star5,
+            star4,
+            path: None,
+            limit: None,
+            pricing: None,
+            script: Vec::new(),
+            spark: None,
+        };
+        let balanced = |tier: TierRules, threshold| TierRules { balance: Some(BalanceRules { threshold }), ..tier };
+
+        RuleSet {
+            name: "star-rail",
+            character: banner(
+                "character",
+                ItemType::Character,
+                tier(0.006, 73, 90, Some((0.5, 1))),
+                balanced(tier(0.051, 8, 10, Some((0.5, 3))), 17),
+            ),
+            weapon: banner(
+                "light cone",
+                ItemType::Weapon,
+                tier(0.008, 65, 80, Some((0.75, 1))),
+                balanced(tier(0.066, 7, 10, Some((0.75, 3))), 14),
+            ),
+            chronicled: None,
+            beginner: Some(BannerRules {
+                limit: Some(50),
+                pricing: Some(PricingRules { multi_size: 10, multi_cost: 8 }),
+                ..banner(
+                    "departure",
+                    ItemType::Character,
+                    tier(0.006, 50, 50, None),
+                    balanced(tier(0.051, 8, 10, None), 17),
+                )
+            }),
+            standard: banner(
+                "standard",
+                ItemType::Character,
+                balanced(tier(0.006, 73, 90, None), 146),
+                balanced(tier(0.051, 8, 10, None), 17),
+            ),
+        }
+    }
+
+    /// Arknights headhunting, 6 star operators taking the place of 5 stars, 5 star
+    /// operators that of 4 stars and the 4 and 3 star operators sharing the 3 star tier.
+    /// The weapon banner slot holds the standard headhunting banner, which has no spark.
+    fn arknights() -> RuleSet {
+        let featured = |featured: Option<(f64, u32)>| featured.map(|(probability, count)| FeaturedRules { probability, count, guarantee: false, radiance: None });
+        let star6 = |featured_6star| TierRules {
+            pity: PityRules {
+                base_probability: 0.02,
+                curve: PityCurve::Increment { threshold: 50, step: 0.02 },
+                max: 99,
+            },
+            featured: featured(featured_6star),
+            balance: None,
+        };
+        // No pity on 5 star operators beyond the first ten pulls
+        let star5 = |featured_5star| TierRules {
+            pity: PityRules {
+                base_probability: 0.08,
+                curve: PityCurve::Step { steps: Vec::new() },
+                max: u32::MAX,
+            },
+            featured: featured(featured_5star),
+            balance: None,
+        };
+        let banner = |name: &str, star5, star4, spark| BannerRules {
+            name: String::from(name),
+            item_type: ItemType::Character,
+            star5,
+            star4,
+            path: None,
+            limit: None,
+            pricing: None,
+            // A 5 star operator or better within the first ten pulls
+            script: vec![ScriptedRules { level: ItemLevel::Star4, featured: None, by: 10, or_higher: true }],
+            spark,
+        };
+
+        RuleSet {
+            name: "arknights",
+            character: banner(
+                "limited headhunting",
+                star6(Some((0.7, 2))),
+                star5(Some((0.5, 1))),
+                Some(SparkRules { threshold: 300, carry_over: false, policy: SparkPolicy::Manual }),
+            ),
+            weapon: banner("standard headhunting", star6(Some((0.5, 2))), star5(Some((0.5, 3))), None),
+            chronicled: None,
+            beginner: None,
+            standard: BannerRules { script: Vec::new(), ..banner("standard", star6(None), star5(None), None) },
+        }
+    }
+}
@@ -2,69 +2,85 @@ mod gacha_traits;
 mod gacha_impl;
 mod user_interface;
 mod common;
+mod batch;
+mod server;
+mod analysis;
+mod pull_log;
 
 use clap::Parser;
 
-use gacha_traits::gacha_enums::*;
-use gacha_impl::*;
-use common::Arguments;
+use batch::items::{self, simulate_items};
+use batch::simulate_batch;
+use common::{Arguments, Command, OutputFormat, SimulateArgs};
+use gacha_impl::Pools;
+use gacha_impl::validation::{validate_pools, Severity};
 use user_interface::*;
 
 fn main() {
     let args = Arguments::parse();
+    if let Some(seed) = args.seed {
+        common::rng::seed(seed);
+    }
+    if let Some(format) = args.explain {
+        gacha_impl::explain::enable(format);
+    }
 
-    println!("Genshin Impact Gacha Simulator @LI Runzhong");
-    if args.interactive {
-        interactive_simulate(&args);
-    } else {
-        println!("Simulating {} times...", args.num_sim);
+    let load_pools = || Pools::from_file(&args.file_path, args.rules.as_deref(), args.as_of.as_deref());
 
-        let mut character_gacha_state = CharacterGachaState::new();
-        let mut weapon_gacha_state = WeaponGachaState::new();
+    match args.command.unwrap_or(Command::Simulate(SimulateArgs::default())) {
+        Command::Simulate(simulate_args) if simulate_args.items && simulate_args.format == OutputFormat::Csv => {
+            let breakdowns = simulate_items(simulate_args.num_sim, &load_pools());
+            items::print_csv(&breakdowns, simulate_args.num_sim, simulate_args.per_pulls);
+        }
+        Command::Simulate(simulate_args) => {
+            println!("Genshin Impact Gacha Simulator @LI Runzhong");
+            println!("Simulating {} times...", simulate_args.num_sim);
 
-        let mut character_up_5star_count = 0u32;
-        let mut character_5star_count = 0u32;
-        let mut weapon_want_5star_count = 0u32;
-        let mut weapon_up_5star_count = 0u32;
-        let mut weapon_5star_count = 0u32;
+            let statistics = simulate_batch(simulate_args.num_sim, simulate_args.banner_pulls, &load_pools());
 
-        for _ in 1..args.num_sim {
-            let result = character_gacha_state.simulate_character_gacha();
-            match result {
-                CharacterGachaType::Up5Star => {
-                    character_up_5star_count += 1;
-                    character_5star_count += 1;
-                }
-                CharacterGachaType::Other5Star => {
-                    character_5star_count += 1;
+            println!("Character up 5 star probability: {}%", statistics.character_up_5star);
+            println!("Character 5 star probability: {}%", statistics.character_5star);
+            println!("Weapon want 5 star probability: {}%", statistics.weapon_want_5star);
+            println!("Weapon up 5 star probability: {}%", statistics.weapon_up_5star);
+            println!("Weapon 5 star probability: {}%", statistics.weapon_5star);
+            if let (Some(charted), Some(total)) = (statistics.chronicled_charted_5star, statistics.chronicled_5star) {
+                println!("Chronicled charted 5 star probability: {}%", charted);
+                println!("Chronicled 5 star probability: {}%", total);
+            }
+            println!("Standard 5 star probability: {}%", statistics.standard_5star);
+            for (banner, spark) in [("Character", &statistics.character_spark), ("Weapon", &statistics.weapon_spark)] {
+                if let Some(spark) = spark {
+                    println!("{} spark exchanges (every {} pulls): {}", banner, spark.threshold, spark.exchanges);
+                    println!("{} up 5 star probability with spark: {}%", banner, spark.up_5star_with_spark);
                 }
-                _ => {}
+            }
+            if let Some(beginner) = &statistics.beginner {
+                println!("Beginner 5 star within {} pulls ({} fates) probability: {}%", beginner.pulls, beginner.fates, beginner.probability_5star);
+            }
+            if simulate_args.items {
+                let breakdowns = simulate_items(simulate_args.num_sim, &load_pools());
+                items::print_table(&breakdowns, simulate_args.num_sim, simulate_args.per_pulls);
             }
         }
-
-        for _ in 1..args.num_sim {
-            let result = weapon_gacha_state.simulate_weapon_gacha();
-            match result {
-                WeaponGachaType::Up5Star(0) => {
-                    weapon_want_5star_count += 1;
-                    weapon_up_5star_count += 1;
-                    weapon_5star_count += 1;
-                }
-                WeaponGachaType::Up5Star(1) => {
-                    weapon_up_5star_count += 1;
-                    weapon_5star_count += 1;
-                }
-                WeaponGachaType::Other5Star => {
-                    weapon_5star_count += 1;
-                }
-                _ => {}
+        Command::Interactive(interactive_args) => {
+            println!("Genshin Impact Gacha Simulator @LI Runzhong");
+            interactive_simulate(&load_pools(), &interactive_args);
+        }
+        Command::Analyze(analyze_args) => analysis::analyze(&load_pools(), &analyze_args),
+        Command::Plan(plan_args) => analysis::plan(&load_pools(), &plan_args),
+        Command::Rates(rates_args) => analysis::star4::rates(&load_pools(), &rates_args),
+        Command::WorstCase(worst_case_args) => analysis::worst_case::worst_case(&load_pools(), &worst_case_args),
+        Command::Import(import_args) => pull_log::import(&load_pools(), &import_args),
+        Command::ValidatePool => {
+            let (_, diagnostics) = validate_pools(&args.file_path, args.rules.as_deref(), args.as_of.as_deref());
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                std::process::exit(1);
             }
+            println!("{} is a valid pool configuration", args.file_path);
         }
-
-        println!("Character up 5 star probability: {}%", character_up_5star_count as f64 * 100. / args.num_sim as f64);
-        println!("Character 5 star probability: {}%", character_5star_count as f64 * 100. / args.num_sim as f64);
-        println!("Weapon want 5 star probability: {}%", weapon_want_5star_count as f64 * 100. / args.num_sim as f64);
-        println!("Weapon up 5 star probability: {}%", weapon_up_5star_count as f64 * 100. / args.num_sim as f64);
-        println!("Weapon 5 star probability: {}%", weapon_5star_count as f64 * 100. / args.num_sim as f64);
+        Command::Serve(serve_args) => server::serve(&serve_args.address, load_pools()),
     }
 }
@@ -0,0 +1,116 @@
+use super::common::{BannerArg, ImportArgs};
+use super::gacha_impl::*;
+use super::gacha_traits::GachaState;
+use super::gacha_traits::gacha_enums::*;
+
+use serde::Deserialize;
+use std::fs;
+
+/// A pull in a log, either the bare item name or an object such as the
+/// history entries returned by the server
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LogEntry {
+    Name(String),
+    Record { name: String },
+}
+
+impl LogEntry {
+    fn name(&self) -> &str {
+        match self {
+            Self::Name(name) => name,
+            Self::Record { name } => name,
+        }
+    }
+}
+
+/// Updates the guarantee of a tier, and its streak of featured rolls lost in a row
+fn record_featured(last_is_up: &mut bool, lost_streak: &mut u32, item: &PoolItem) {
+    match (item.up_index, *last_is_up) {
+        (Some(_), true) => *lost_streak = 0,
+        (Some(_), false) => {}
+        (None, _) => *lost_streak += 1,
+    }
+    *last_is_up = item.up_index.is_some();
+}
+
+/// Updates the pity counters of a state as the simulator would have for `item`
+fn record(state: &mut GachaState, item: &PoolItem) {
+    state.pulls += 1;
+    state.since_last_5star += 1;
+    state.since_last_4star += 1;
+    state.since_last_4star_character += 1;
+    state.since_last_4star_weapon += 1;
+    match item.level {
+        ItemLevel::Star5 => {
+            state.since_last_5star = 0;
+            record_featured(&mut state.last_5star_is_up, &mut state.lost_5star_streak, item);
+        }
+        ItemLevel::Star4 => {
+            state.since_last_4star = 0;
+            record_featured(&mut state.last_4star_is_up, &mut state.lost_4star_streak, item);
+            match item.item_type {
+                ItemType::Character => state.since_last_4star_character = 0,
+                ItemType::Weapon => state.since_last_4star_weapon = 0,
+            }
+        }
+        ItemLevel::Star3 => {}
+    }
+}
+
+fn find_item(pools: &Pools, banner: BannerArg, name: &str) -> Option<PoolItem> {
+    match banner {
+        BannerArg::Character => pools.character1.find_item(name, ItemType::Character)
+            .or_else(|| pools.character2.find_item(name, ItemType::Character)),
+        BannerArg::Weapon => pools.weapon.find_item(name, ItemType::Weapon),
+    }
+}
+
+/// Replays a pull log and serializes the resulting banner state
+fn reconstruct_state(pools: &Pools, args: &ImportArgs, log: &[LogEntry]) -> Result<String, String> {
+    let rules = match args.banner {
+        BannerArg::Character => pools.character_rules(),
+        BannerArg::Weapon => pools.weapon_rules(),
+    };
+    let mut state = GachaState::new();
+    let mut fate_points = 0;
+    for (index, entry) in log.iter().enumerate() {
+        let item = find_item(pools, args.banner, entry.name())
+            .ok_or_else(|| format!("Pull {}: {} is not in the {:?} pool", index + 1, entry.name(), args.banner))?;
+        record(&mut state, &item);
+        if let (ItemLevel::Star5, Some(path)) = (item.level, args.path) {
+            fate_points = if item.up_index == Some(path) { 0 } else { fate_points + 1 };
+        }
+    }
+    // The log does not say which spark points were already exchanged
+    if rules.spark.is_some() {
+        state.spark_points = state.pulls;
+    }
+    if let (Some(path_rules), Some(path)) = (&rules.path, args.path) {
+        state.want_5star_state = WantState::Want(path, fate_points.min(path_rules.max_fate_points));
+    }
+    serde_json::to_string_pretty(&state).map_err(|e| e.to_string())
+}
+
+pub fn import(pools: &Pools, args: &ImportArgs) {
+    let log = fs::read_to_string(&args.log_path)
+        .unwrap_or_else(|_| panic!("Unable to read file: {}", args.log_path));
+    let log: Vec<LogEntry> = serde_json::from_str(&log)
+        .unwrap_or_else(|e| panic!("Unable to parse pull log {}: {}", args.log_path, e));
+
+    let state = match reconstruct_state(pools, args, &log) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match &args.output {
+        Some(output) => {
+            fs::write(output, state)
+                .unwrap_or_else(|e| panic!("Unable to write file {}: {}", output, e));
+            println!("Imported {} pulls into {}", log.len(), output);
+        }
+        None => println!("{}", state),
+    }
+}
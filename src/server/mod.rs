@@ -0,0 +1,463 @@
+use super::batch::simulate_batch;
+use super::gacha_impl::*;
+use super::gacha_impl::validation::{validate_pool, Severity};
+use super::gacha_traits::Banner;
+use super::gacha_traits::rule_sets::*;
+use super::gacha_traits::gacha_enums::*;
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const MAX_PULLS_PER_REQUEST: u32 = 100_000;
+const MAX_SIMULATIONS_PER_REQUEST: u32 = 10_000_000;
+/// Highest counter a client-supplied state may hold, leaving room for the session to count on
+const MAX_STATE_COUNT: u32 = 1 << 30;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BannerKind {
+    Character1,
+    Character2,
+    Weapon,
+    Chronicled,
+    Beginner,
+}
+
+#[derive(Serialize)]
+struct PullRecord {
+    name: String,
+    rarity: u32,
+    item_type: ItemType,
+    is_up: bool,
+    /// Pulls since the previous 5 star, counting this one
+    pity: u32,
+    /// Whether the item was exchanged for spark points rather than pulled
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    exchanged: bool,
+}
+
+struct Session {
+    banner: BannerKind,
+    gacha: Box<dyn Banner>,
+    pool: BannerPool,
+    history: Vec<PullRecord>,
+    /// Fates spent, each pull request paying as a multi-pull where the banner discounts them
+    fates: u32,
+}
+
+#[derive(Deserialize)]
+struct CreateSessionRequest {
+    banner: BannerKind,
+    /// Inline pool overriding the one from the pool configuration file
+    pool: Option<Value>,
+    /// Pity state to start from, as returned in `state` by the status endpoint
+    state: Option<Value>,
+    /// Rule set overriding the one of the pool configuration file
+    rules: Option<String>,
+    /// Index of the featured item to chart on the Epitomized Path or Chronicled Wish course
+    path: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    count: u32,
+}
+
+#[derive(Deserialize)]
+struct ExchangeRequest {
+    index: u32,
+}
+
+#[derive(Deserialize)]
+struct AnalyzeRequest {
+    num_sim: u32,
+    /// Pulls made on each banner before the next one, for sparks that do not carry over
+    banner_pulls: Option<u32>,
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> ApiError {
+        ApiError { status: 400, message: message.into() }
+    }
+
+    fn not_found(message: impl Into<String>) -> ApiError {
+        ApiError { status: 404, message: message.into() }
+    }
+}
+
+type ApiResult = Result<Value, ApiError>;
+
+fn rarity(level: ItemLevel) -> u32 {
+    match level {
+        ItemLevel::Star3 => 3,
+        ItemLevel::Star4 => 4,
+        ItemLevel::Star5 => 5,
+    }
+}
+
+fn parse_value<T: DeserializeOwned>(value: Value, what: &str) -> Result<T, ApiError> {
+    serde_json::from_value(value).map_err(|e| ApiError::bad_request(format!("Invalid {}: {}", what, e)))
+}
+
+impl Session {
+    fn new(request: CreateSessionRequest, pools: &Pools) -> Result<Session, ApiError> {
+        let rule_set = match &request.rules {
+            Some(name) => RuleSet::named(name)
+                .ok_or_else(|| ApiError::bad_request(format!("Unknown rule set {}, expected one of {}", name, RULE_SETS.join(", "))))?,
+            None => pools.rule_set(),
+        };
+        let (rules, pool) = match request.banner {
+            BannerKind::Character1 => (rule_set.character, &pools.character1),
+            BannerKind::Character2 => (rule_set.character, &pools.character2),
+            BannerKind::Weapon => (rule_set.weapon, &pools.weapon),
+            BannerKind::Chronicled => match (rule_set.chronicled, &pools.chronicled) {
+                (Some(rules), Some(pool)) => (rules, pool),
+                _ => return Err(ApiError::bad_request(format!("No Chronicled Wish under the {} rule set and pool configuration", rule_set.name))),
+            },
+            BannerKind::Beginner => match (rule_set.beginner, &pools.beginner) {
+                (Some(rules), Some(pool)) => (rules, pool),
+                _ => return Err(ApiError::bad_request(format!("No Beginners' Wish under the {} rule set and pool configuration", rule_set.name))),
+            },
+        };
+        let pool: BannerPool = match request.pool {
+            Some(pool) => {
+                let mut pool: BannerPool = parse_value(pool, "pool")?;
+                pool.restrict(pools.as_of.as_deref());
+                pool
+            }
+            None => pool.clone(),
+        };
+        // The pool file was only checked against its own rule set, and an inline pool not at all
+        let rules = pool.rules(rules);
+        let errors: Vec<String> = validate_pool("pool", &pool, &rules, pools.as_of.as_deref()).into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        if !errors.is_empty() {
+            return Err(ApiError::bad_request(format!("Invalid pool: {}", errors.join("; "))));
+        }
+        let mut gacha = GachaBanner::new(rules);
+        if let Some(state) = request.state {
+            gacha.state = parse_value(state, "state")?;
+            check_counters(&gacha)?;
+            let featured = gacha.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+            if let WantState::Want(index, _) = gacha.state.want_5star_state {
+                if index >= featured {
+                    return Err(ApiError::bad_request(format!(
+                        "Invalid state: want_5star_state charts item {} but the {} banner features {}", index, gacha.rules.name, featured,
+                    )));
+                }
+            }
+        }
+        if let Some(index) = request.path {
+            let featured = gacha.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+            if gacha.rules.path.is_none() || index >= featured {
+                return Err(ApiError::bad_request(format!("Path index must be less than {} on a banner with a path", featured)));
+            }
+            gacha.chart_path(Some(index));
+        }
+        Ok(Session {
+            banner: request.banner,
+            gacha: Box::new(gacha),
+            pool,
+            history: Vec::new(),
+            fates: 0,
+        })
+    }
+
+    fn pull(&mut self, count: u32) -> Result<Vec<&PullRecord>, ApiError> {
+        if let Some(remaining) = self.gacha.remaining() {
+            if count > remaining {
+                return Err(ApiError::bad_request(format!("Only {} pulls left on this banner", remaining)));
+            }
+        }
+        self.fates += self.gacha.rules().cost(count);
+        let start = self.history.len();
+        for _ in 0..count {
+            let pity = self.gacha.state().since_last_5star + 1;
+            let result = self.gacha.pull();
+            self.record(result, pity, false);
+            if let Some(result) = self.gacha.auto_exchange() {
+                self.record(result, 0, true);
+            }
+        }
+        Ok(self.history[start..].iter().collect())
+    }
+
+    fn record(&mut self, result: GachaResult, pity: u32, exchanged: bool) {
+        self.history.push(PullRecord {
+            name: result.pick_item_name(&self.pool).to_string(),
+            rarity: rarity(result.get_item_level()),
+            item_type: result.get_item_type(self.gacha.rules(), &self.pool),
+            is_up: result.is_up(),
+            pity,
+            exchanged,
+        });
+    }
+
+    /// Exchanges the spark points for the featured 5 star `index`
+    fn exchange(&mut self, index: u32) -> Result<&PullRecord, ApiError> {
+        let Some(spark) = self.gacha.rules().spark.clone() else {
+            return Err(ApiError::bad_request("This banner has no spark"));
+        };
+        match self.gacha.exchange(index) {
+            Some(result) => {
+                self.record(result, 0, true);
+                Ok(&self.history[self.history.len() - 1])
+            }
+            None => Err(ApiError::bad_request(format!(
+                "Exchanging needs {} spark points and a featured 5 star index, found {} points and index {}",
+                spark.threshold, self.gacha.state().spark_points, index,
+            ))),
+        }
+    }
+
+    /// Starts the session over from fresh pity, keeping its banner and pool
+    fn reset(&mut self) {
+        self.gacha.reset();
+        self.history.clear();
+        self.fates = 0;
+    }
+
+    fn status(&self, id: u64) -> Value {
+        let state = self.gacha.state();
+        let count = |rarity: u32| self.history.iter().filter(|r| r.rarity == rarity).count();
+        json!({
+            "id": id,
+            "banner": self.banner,
+            "pulls": state.pulls,
+            "fates": self.fates,
+            "remaining": self.gacha.remaining(),
+            "count_5star": count(5),
+            "count_4star": count(4),
+            "since_last_5star": state.since_last_5star,
+            "since_last_4star": state.since_last_4star,
+            "guaranteed_5star": !state.last_5star_is_up,
+            "guaranteed_4star": !state.last_4star_is_up,
+            "spark_points": self.gacha.rules().spark.as_ref().map(|_| state.spark_points),
+            "state": state,
+        })
+    }
+}
+
+/// Rejects a client-supplied state whose pity counters are past hard pity, or whose other
+/// counters are so high the session would overflow them
+fn check_counters(gacha: &GachaBanner) -> Result<(), ApiError> {
+    let state = &gacha.state;
+    let pities = [
+        ("since_last_5star", state.since_last_5star, gacha.rules.star5.pity.max),
+        ("since_last_4star", state.since_last_4star, gacha.rules.star4.pity.max),
+    ];
+    for (name, count, max) in pities {
+        if count >= max.min(MAX_STATE_COUNT) {
+            return Err(ApiError::bad_request(format!(
+                "Invalid state: {} is {} but must be less than {}", name, count, max.min(MAX_STATE_COUNT),
+            )));
+        }
+    }
+    let counters = [
+        ("pulls", state.pulls),
+        ("since_last_5star_character", state.since_last_5star_character),
+        ("since_last_5star_weapon", state.since_last_5star_weapon),
+        ("since_last_4star_character", state.since_last_4star_character),
+        ("since_last_4star_weapon", state.since_last_4star_weapon),
+        ("lost_5star_streak", state.lost_5star_streak),
+        ("lost_4star_streak", state.lost_4star_streak),
+        ("spark_points", state.spark_points),
+    ];
+    match counters.into_iter().find(|&(_, count)| count > MAX_STATE_COUNT) {
+        Some((name, count)) => Err(ApiError::bad_request(format!(
+            "Invalid state: {} is {} but must be at most {}", name, count, MAX_STATE_COUNT,
+        ))),
+        None => Ok(()),
+    }
+}
+
+struct ApiServer {
+    pools: Pools,
+    sessions: HashMap<u64, Session>,
+    next_id: u64,
+}
+
+impl ApiServer {
+    fn session(&mut self, id: &str) -> Result<(u64, &mut Session), ApiError> {
+        let id: u64 = id.parse().map_err(|_| ApiError::bad_request(format!("Invalid session id: {}", id)))?;
+        match self.sessions.get_mut(&id) {
+            Some(session) => Ok((id, session)),
+            None => Err(ApiError::not_found(format!("No such session: {}", id))),
+        }
+    }
+
+    fn handle(&mut self, method: &Method, path: &str, body: &str) -> ApiResult {
+        let segments: Vec<&str> = path.split('?').next().unwrap_or("").split('/').filter(|s| !s.is_empty()).collect();
+        let parse_body = || -> Result<Value, ApiError> {
+            if body.trim().is_empty() {
+                Ok(json!({}))
+            } else {
+                serde_json::from_str(body).map_err(|e| ApiError::bad_request(format!("Invalid JSON body: {}", e)))
+            }
+        };
+        match (method, segments.as_slice()) {
+            (Method::Post, ["sessions"]) => {
+                let request: CreateSessionRequest = parse_value(parse_body()?, "request")?;
+                let session = Session::new(request, &self.pools)?;
+                let id = self.next_id;
+                self.next_id += 1;
+                let status = session.status(id);
+                self.sessions.insert(id, session);
+                Ok(status)
+            }
+            (Method::Get, ["sessions"]) => {
+                let mut ids: Vec<u64> = self.sessions.keys().copied().collect();
+                ids.sort();
+                Ok(json!({ "sessions": ids }))
+            }
+            (Method::Get, ["sessions", id]) => {
+                let (id, session) = self.session(id)?;
+                Ok(session.status(id))
+            }
+            (Method::Delete, ["sessions", id]) => {
+                let (id, _) = self.session(id)?;
+                self.sessions.remove(&id);
+                Ok(json!({ "deleted": id }))
+            }
+            (Method::Post, ["sessions", id, "pull"]) => {
+                let request: PullRequest = parse_value(parse_body()?, "request")?;
+                if request.count > MAX_PULLS_PER_REQUEST {
+                    return Err(ApiError::bad_request(format!("At most {} pulls per request", MAX_PULLS_PER_REQUEST)));
+                }
+                let (id, session) = self.session(id)?;
+                let results = json!(session.pull(request.count)?);
+                Ok(json!({ "results": results, "status": session.status(id) }))
+            }
+            (Method::Post, ["sessions", id, "exchange"]) => {
+                let request: ExchangeRequest = parse_value(parse_body()?, "request")?;
+                let (id, session) = self.session(id)?;
+                let result = json!(session.exchange(request.index)?);
+                Ok(json!({ "result": result, "status": session.status(id) }))
+            }
+            (Method::Post, ["sessions", id, "next"]) => {
+                let (id, session) = self.session(id)?;
+                session.gacha.next_banner();
+                Ok(session.status(id))
+            }
+            (Method::Post, ["sessions", id, "reset"]) => {
+                let (id, session) = self.session(id)?;
+                session.reset();
+                Ok(session.status(id))
+            }
+            (Method::Get, ["sessions", id, "history"]) => {
+                let (_, session) = self.session(id)?;
+                Ok(json!({ "history": session.history }))
+            }
+            (Method::Post, ["analyze"]) => {
+                let request: AnalyzeRequest = parse_value(parse_body()?, "request")?;
+                if request.num_sim == 0 || request.num_sim > MAX_SIMULATIONS_PER_REQUEST {
+                    return Err(ApiError::bad_request(format!("num_sim must be between 1 and {}", MAX_SIMULATIONS_PER_REQUEST)));
+                }
+                if request.banner_pulls == Some(0) {
+                    return Err(ApiError::bad_request("banner_pulls must be at least 1"));
+                }
+                Ok(json!(simulate_batch(request.num_sim, request.banner_pulls, &self.pools)))
+            }
+            _ => Err(ApiError::not_found(format!("No route for {} {}", method, path))),
+        }
+    }
+
+    fn respond(&mut self, mut request: Request) {
+        let mut body = String::new();
+        let result = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => self.handle(request.method(), request.url(), &body),
+            Err(e) => Err(ApiError::bad_request(format!("Unable to read body: {}", e))),
+        };
+        let (status, value) = match result {
+            Ok(value) => (200, value),
+            Err(error) => (error.status, json!({ "error": error.message })),
+        };
+        let content_type = Header::from_bytes("Content-Type", "application/json").expect("Invalid header");
+        let response = Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(e) = request.respond(response) {
+            eprintln!("Unable to send response: {}", e);
+        }
+    }
+}
+
+pub fn serve(address: &str, pools: Pools) {
+    let server = Server::http(address)
+        .unwrap_or_else(|e| panic!("Unable to listen on {}: {}", address, e));
+    let mut api = ApiServer {
+        pools,
+        sessions: HashMap::new(),
+        next_id: 1,
+    };
+    println!("Listening on http://{}", address);
+    for request in server.incoming_requests() {
+        api.respond(request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> ApiServer {
+        ApiServer {
+            pools: Pools::from_file("pool.json", None, None),
+            sessions: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    #[test]
+    fn creates_a_session_and_pulls() {
+        let mut api = api();
+        let status = api.handle(&Method::Post, "/sessions", r#"{"banner": "weapon"}"#).unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(status["id"], 1);
+        assert_eq!(status["pulls"], 0);
+        let pulled = api.handle(&Method::Post, "/sessions/1/pull", r#"{"count": 10}"#).unwrap_or_else(|e| panic!("{}", e.message));
+        let results = pulled["results"].as_array().expect("No results");
+        assert!(results.len() >= 10);
+        assert_eq!(results.iter().filter(|r| r.get("exchanged").is_none()).count(), 10);
+        assert_eq!(pulled["status"]["pulls"], 10);
+        assert_eq!(pulled["status"]["state"]["pulls"], 10);
+        let error = api.handle(&Method::Post, "/sessions/2/pull", r#"{"count": 1}"#).expect_err("Pulled on a missing session");
+        assert_eq!(error.status, 404);
+    }
+
+    #[test]
+    fn rejects_malformed_states() {
+        let mut api = api();
+        let fresh = api.handle(&Method::Post, "/sessions", r#"{"banner": "character1"}"#).unwrap_or_else(|e| panic!("{}", e.message));
+        let create = |api: &mut ApiServer, field: &str, value: Value| {
+            let mut state = fresh["state"].clone();
+            state[field] = value;
+            api.handle(&Method::Post, "/sessions", &json!({ "banner": "character1", "state": state }).to_string())
+        };
+        let accepted = create(&mut api, "since_last_5star", json!(89)).unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(accepted["since_last_5star"], 89);
+        for (field, value) in [
+            ("since_last_5star", json!(90)),
+            ("since_last_5star", json!(u32::MAX)),
+            ("since_last_4star", json!(10)),
+            ("pulls", json!(u32::MAX)),
+            ("spark_points", json!(u32::MAX)),
+            ("since_last_5star", json!(-1)),
+            ("want_5star_state", json!({ "Want": [7, 2] })),
+        ] {
+            let error = create(&mut api, field, value.clone()).expect_err(&format!("Accepted {} = {}", field, value));
+            assert_eq!(error.status, 400, "{}", error.message);
+        }
+        let error = api.handle(&Method::Post, "/sessions", "{").expect_err("Accepted a malformed body");
+        assert_eq!(error.status, 400);
+    }
+}
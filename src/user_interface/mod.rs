@@ -1,68 +1,416 @@
-use super::gacha_traits::*;
-use super::common::Arguments;
+mod tui;
+
+use super::common::InteractiveArgs;
 use super::gacha_impl::*;
+use super::gacha_traits::Banner;
 
+use serde::{Deserialize, Serialize};
 use std::{fs, io};
+use std::io::IsTerminal;
+
+/// Most pulls a single input makes, each result being printed
+const MAX_PULLS_AT_ONCE: u32 = 100_000;
 
 enum InteractiveStage {
     Start,
-    RealModeCharacterPool2,
+    RealMode,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum BannerChoice {
+    Character1,
+    Character2,
+    Weapon,
+    Chronicled,
+    Beginner,
+}
+
+impl BannerChoice {
+    const ALL: [BannerChoice; 5] = [Self::Character1, Self::Character2, Self::Weapon, Self::Chronicled, Self::Beginner];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Character1 => "角色活动祈愿",
+            Self::Character2 => "角色活动祈愿-2",
+            Self::Weapon => "武器活动祈愿",
+            Self::Chronicled => "集录祈愿",
+            Self::Beginner => "新手祈愿",
+        }
+    }
+
+    fn pool<'a>(&self, pools: &'a Pools) -> &'a BannerPool {
+        match self {
+            Self::Character1 => &pools.character1,
+            Self::Character2 => &pools.character2,
+            Self::Weapon => &pools.weapon,
+            Self::Chronicled => pools.chronicled.as_ref().expect("No Chronicled Wish pool"),
+            Self::Beginner => pools.beginner.as_ref().expect("No Beginners' Wish pool"),
+        }
+    }
+}
+
+/// Everything needed to resume pulling from a given point
+#[derive(Clone, Serialize, Deserialize)]
+struct GachaSnapshot {
+    banner: BannerChoice,
+    character: GachaBanner,
+    weapon: GachaBanner,
+    /// Chronicled Wish, when the rule set and pool configuration have one
+    #[serde(default)]
+    chronicled: Option<GachaBanner>,
+    /// Beginners' Wish, when the rule set and pool configuration have one
+    #[serde(default)]
+    beginner: Option<GachaBanner>,
+}
+
+impl GachaSnapshot {
+    fn new(pool_config: &Pools) -> GachaSnapshot {
+        GachaSnapshot {
+            banner: BannerChoice::Character2,
+            character: GachaBanner::new(pool_config.character_rules()),
+            weapon: GachaBanner::new(pool_config.weapon_rules()),
+            chronicled: pool_config.chronicled_rules().map(GachaBanner::new),
+            beginner: pool_config.beginner_rules().map(GachaBanner::new),
+        }
+    }
+
+    /// Banners that can be pulled on, the Chronicled and Beginners' Wishes only where there are some
+    fn banners(&self) -> Vec<BannerChoice> {
+        BannerChoice::ALL.into_iter()
+            .filter(|banner| match banner {
+                BannerChoice::Chronicled => self.chronicled.is_some(),
+                BannerChoice::Beginner => self.beginner.is_some(),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Whether the snapshot can be pulled on with `pool_config`, which has to hold every banner
+    /// it has and the one it is on
+    fn fits(&self, pool_config: &Pools) -> bool {
+        self.banners().contains(&self.banner)
+            && (self.chronicled.is_none() || pool_config.chronicled.is_some())
+            && (self.beginner.is_none() || pool_config.beginner.is_some())
+    }
+
+    fn current(&self) -> &GachaBanner {
+        match self.banner {
+            BannerChoice::Character1 | BannerChoice::Character2 => &self.character,
+            BannerChoice::Weapon => &self.weapon,
+            BannerChoice::Chronicled => self.chronicled.as_ref().expect("No Chronicled Wish"),
+            BannerChoice::Beginner => self.beginner.as_ref().expect("No Beginners' Wish"),
+        }
+    }
+
+    /// The banner currently pulled on, the two character banners sharing their pity
+    fn active(&mut self) -> &mut dyn Banner {
+        match self.banner {
+            BannerChoice::Character1 | BannerChoice::Character2 => &mut self.character,
+            BannerChoice::Weapon => &mut self.weapon,
+            BannerChoice::Chronicled => self.chronicled.as_mut().expect("No Chronicled Wish"),
+            BannerChoice::Beginner => self.beginner.as_mut().expect("No Beginners' Wish"),
+        }
+    }
+
+    /// The banner charted by the path command, the weapon banner unless on the Chronicled Wish
+    fn charted(&mut self) -> (&mut GachaBanner, BannerChoice) {
+        match self.banner {
+            BannerChoice::Chronicled => (self.chronicled.as_mut().expect("No Chronicled Wish"), BannerChoice::Chronicled),
+            _ => (&mut self.weapon, BannerChoice::Weapon),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotNode {
+    parent: Option<usize>,
+    label: String,
+    snapshot: GachaSnapshot,
+}
+
+/// Snapshots taken in real mode, forming a tree of "what if" branches
+#[derive(Serialize, Deserialize)]
+struct SnapshotTree {
+    nodes: Vec<SnapshotNode>,
+    current: usize,
+}
+
+impl SnapshotTree {
+    fn new(root: GachaSnapshot) -> SnapshotTree {
+        SnapshotTree {
+            nodes: vec![SnapshotNode {
+                parent: None,
+                label: String::from("初始状态"),
+                snapshot: root,
+            }],
+            current: 0,
+        }
+    }
+
+    /// Records a snapshot as a child of the current node and moves onto it
+    fn push(&mut self, label: String, snapshot: GachaSnapshot) -> usize {
+        self.nodes.push(SnapshotNode {
+            parent: Some(self.current),
+            label,
+            snapshot,
+        });
+        self.current = self.nodes.len() - 1;
+        self.current
+    }
+
+    fn checkout(&mut self, id: usize) -> Option<GachaSnapshot> {
+        let node = self.nodes.get(id)?;
+        self.current = id;
+        Some(node.snapshot.clone())
+    }
+
+    fn print(&self) {
+        self.print_node(0, 0);
+    }
+
+    fn print_node(&self, id: usize, depth: usize) {
+        let node = &self.nodes[id];
+        let marker = if id == self.current { "*" } else { " " };
+        println!("{}{}[{}] {} ({})", "  ".repeat(depth), marker, id, node.label, node.snapshot.banner.name());
+        for (child, _) in self.nodes.iter().enumerate().filter(|(_, n)| n.parent == Some(id)) {
+            self.print_node(child, depth + 1);
+        }
+    }
 }
 
 struct InteractiveState {
     stage: InteractiveStage,
+    gacha: GachaSnapshot,
+    snapshots: SnapshotTree,
+    /// Whether pulls were made since the current snapshot was taken or restored
+    dirty: bool,
 }
 
 impl InteractiveState {
-    fn new() -> InteractiveState {
+    fn new(pool_config: &Pools) -> InteractiveState {
+        let gacha = GachaSnapshot::new(pool_config);
         InteractiveState {
             stage: InteractiveStage::Start,
+            snapshots: SnapshotTree::new(gacha.clone()),
+            gacha,
+            dirty: false,
+        }
+    }
+
+    fn pull(&mut self, num: u32, pool_config: &Pools) {
+        if num > MAX_PULLS_AT_ONCE {
+            println!("一次最多祈愿 {} 次", MAX_PULLS_AT_ONCE);
+            return;
+        }
+        let pool = self.gacha.banner.pool(pool_config);
+        let pulls_before = self.gacha.current().state.pulls;
+        let results = self.gacha.active().pull_n(num);
+        for result in &results {
+            print!("{} ", result.get_item_name(pool));
+        }
+        println!();
+        let banner = self.gacha.current();
+        let pulled = banner.state.pulls - pulls_before;
+        if banner.rules.pricing.is_some() && pulled > 0 {
+            println!("消耗 {} 祈愿之缘", banner.rules.cost(pulled));
+        }
+        if results.len() > pulled as usize {
+            println!("已自动兑换 {} 次", results.len() - pulled as usize);
+        }
+        if pulled < num {
+            println!("已达到本卡池的祈愿次数上限");
+        }
+        print_odds(banner);
+        self.dirty = true;
+    }
+
+    /// Rolls back to the current snapshot, or to its parent if nothing changed since
+    fn undo(&mut self) {
+        let target = if self.dirty {
+            Some(self.snapshots.current)
+        } else {
+            self.snapshots.nodes[self.snapshots.current].parent
+        };
+        match target {
+            Some(id) => self.checkout(id),
+            None => println!("已经是初始状态"),
+        }
+    }
+
+    fn checkout(&mut self, id: usize) {
+        match self.snapshots.checkout(id) {
+            Some(snapshot) => {
+                self.gacha = snapshot;
+                self.dirty = false;
+                println!("已回到快照 [{}] {}", id, self.snapshots.nodes[id].label);
+            }
+            None => println!("快照 [{}] 不存在", id),
+        }
+    }
+
+    fn run_command(&mut self, command: &str, pool_config: &Pools) {
+        let mut words = command.split_whitespace();
+        let Some(verb) = words.next() else {
+            return;
+        };
+        let rest = words.collect::<Vec<_>>().join(" ");
+        match (verb, rest.as_str()) {
+            ("banner", "c1") => self.gacha.banner = BannerChoice::Character1,
+            ("banner", "c2") => self.gacha.banner = BannerChoice::Character2,
+            ("banner", "w") => self.gacha.banner = BannerChoice::Weapon,
+            ("banner", "cw") if self.gacha.chronicled.is_some() => self.gacha.banner = BannerChoice::Chronicled,
+            ("banner", "cw") => println!("当前规则或卡池配置没有集录祈愿"),
+            ("banner", "b") if self.gacha.beginner.is_some() => self.gacha.banner = BannerChoice::Beginner,
+            ("banner", "b") => println!("当前规则或卡池配置没有新手祈愿"),
+            ("path", "none") => self.gacha.charted().0.chart_path(None),
+            ("path", index) => {
+                let (banner, choice) = self.gacha.charted();
+                let featured = banner.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+                match index.parse() {
+                    Ok(index) if index < featured => {
+                        banner.chart_path(Some(index));
+                        println!("已定轨 {}", choice.pool(pool_config).get_up_5star_name(index));
+                    }
+                    _ => println!("请输入 path 0 至 path {} 或 path none", featured.saturating_sub(1)),
+                }
+            }
+            ("spark", index) => {
+                let pool = self.gacha.banner.pool(pool_config);
+                let banner = self.gacha.active();
+                let Some(spark) = banner.rules().spark.clone() else {
+                    println!("当前卡池没有兑换");
+                    return;
+                };
+                let featured = banner.rules().star5.featured.as_ref().map_or(0, |featured| featured.count);
+                match index.parse() {
+                    Ok(index) if index < featured => match banner.exchange(index) {
+                        Some(result) => {
+                            println!("已兑换 {}", result.get_item_name(pool));
+                            self.dirty = true;
+                        }
+                        None => println!("兑换需要 {} 抽，当前 {} 抽", spark.threshold, banner.state().spark_points),
+                    },
+                    _ => println!("请输入 spark 0 至 spark {}", featured.saturating_sub(1)),
+                }
+            }
+            ("next", "") => {
+                self.gacha.active().next_banner();
+                self.dirty = true;
+                println!("已进入下一期卡池");
+            }
+            ("snap", label) => {
+                let label = if label.is_empty() { format!("快照 {}", self.snapshots.nodes.len()) } else { label.to_string() };
+                let id = self.snapshots.push(label, self.gacha.clone());
+                self.dirty = false;
+                println!("已保存快照 [{}]", id);
+            }
+            ("undo", "") => self.undo(),
+            ("fork", id) => match id.parse() {
+                Ok(id) => self.checkout(id),
+                Err(_) => println!("请输入快照编号"),
+            },
+            ("tree", "") => self.snapshots.print(),
+            ("save", file_path) if !file_path.is_empty() => {
+                let json = serde_json::to_string_pretty(&self.snapshots)
+                    .expect("Unable to serialize snapshots");
+                match fs::write(file_path, json) {
+                    Ok(()) => println!("已导出快照到 {}", file_path),
+                    Err(e) => println!("无法写入文件 {}: {}", file_path, e),
+                }
+            }
+            ("load", file_path) if !file_path.is_empty() => {
+                let snapshots = fs::read_to_string(file_path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| serde_json::from_str::<SnapshotTree>(&json).map_err(|e| e.to_string()));
+                match snapshots {
+                    Ok(snapshots) if snapshots.current >= snapshots.nodes.len() => println!("快照文件 {} 已损坏", file_path),
+                    Ok(snapshots) if !snapshots.nodes.iter().all(|node| node.snapshot.fits(pool_config)) => {
+                        println!("快照文件 {} 中的卡池与当前卡池配置不符", file_path);
+                    }
+                    Ok(snapshots) => {
+                        self.snapshots = snapshots;
+                        self.checkout(self.snapshots.current);
+                    }
+                    Err(e) => println!("无法读取快照文件 {}: {}", file_path, e),
+                }
+            }
+            _ => print_real_mode_help(),
         }
     }
 }
 
-pub fn interactive_simulate(args: &Arguments) {
-    let pool_config = fs::read_to_string(&args.file_path)
-        .expect(&format!("Unable to read file: {}", args.file_path));
-    let pool_config: Pools = serde_json::from_str(&pool_config)
-        .expect("Unable to parse json");
-    let mut interactive_state = InteractiveState::new();
-    let mut character_gacha_state = CharacterGachaState::new();
+/// Pulls in a multi-pull, for the odds shown after each batch
+const MULTI_PULL: u32 = 10;
+
+/// Prints the odds of the next pulls on a banner and how far its pity is
+fn print_odds(banner: &GachaBanner) {
+    let odds = banner.odds(MULTI_PULL);
+    println!(
+        "下一抽 5★ {:.2}%  4★ {:.2}%  下一个十连 5★ {:.2}%  4★ {:.2}%",
+        odds.next_5star * 100., odds.next_4star * 100., odds.multi_5star * 100., odds.multi_4star * 100.,
+    );
+    if let Some(up_5star) = odds.up_5star {
+        println!("下一个 5★ 为 UP 的概率 {:.2}%", up_5star * 100.);
+    }
+    match odds.to_soft_pity {
+        Some(0) => println!("已进入软保底，距硬保底 {} 抽", odds.to_hard_pity),
+        Some(pulls) => println!("距软保底 {} 抽，距硬保底 {} 抽", pulls, odds.to_hard_pity),
+        None => println!("距硬保底 {} 抽", odds.to_hard_pity),
+    }
+}
+
+fn print_real_mode_help() {
+    println!("输入抽数进行祈愿，或输入命令：");
+    println!("  banner c1|c2|w|cw|b 切换卡池");
+    println!("  path <编号>|none 武器池或集录祈愿定轨");
+    println!("  spark <编号>     用累计抽数兑换 UP 角色");
+    println!("  next             进入下一期卡池");
+    println!("  snap [名称]      保存快照");
+    println!("  undo             回退到上一个快照");
+    println!("  fork <编号>      从指定快照开始新的分支");
+    println!("  tree             显示快照树");
+    println!("  save|load <文件> 导出或导入快照树");
+    println!("  q                退出");
+}
+
+pub fn interactive_simulate(pool_config: &Pools, args: &InteractiveArgs) {
+    // The full-screen UI would draw over the traces written to stderr
+    if !args.plain && !explain::enabled() && io::stdout().is_terminal() && io::stdin().is_terminal() {
+        tui::run(pool_config).expect("Unable to run terminal UI");
+        return;
+    }
+    let mut interactive_state = InteractiveState::new(pool_config);
     loop {
         match interactive_state.stage {
             InteractiveStage::Start => {
                 println!("请选择模拟模式：(1) 真实模式 (2) 概率分析 (q) 退出");
                 let mut mode = String::new();
-                io::stdin().read_line(&mut mode)
+                let read = io::stdin().read_line(&mut mode)
                     .expect("Unable to read line from stdin");
-                if mode.trim().starts_with("q") {
+                if read == 0 || mode.trim().starts_with("q") {
                     break;
                 }
                 match mode.trim().parse() {
                     Ok(1) => {
-                        interactive_state.stage = InteractiveStage::RealModeCharacterPool2;
+                        print_real_mode_help();
+                        interactive_state.stage = InteractiveStage::RealMode;
                     }
                     _ => continue
                 };
             }
-            InteractiveStage::RealModeCharacterPool2 => {
+            InteractiveStage::RealMode => {
+                println!("[{}]", interactive_state.gacha.banner.name());
                 let mut num = String::new();
-                io::stdin().read_line(&mut num)
+                let read = io::stdin().read_line(&mut num)
                     .expect("Unable to read line");
-                if num.trim().starts_with("q") {
+                if read == 0 || num.trim().starts_with("q") {
                     break
                 }
-                let num: u32 = match num.trim().parse() {
-                    Ok(num) => num,
-                    Err(_) => continue
+                match num.trim().parse() {
+                    Ok(num) => interactive_state.pull(num, pool_config),
+                    Err(_) => interactive_state.run_command(num.trim(), pool_config),
                 };
-                for _ in 0..num {
-                    let result = character_gacha_state.simulate_character_gacha();
-                    let name = result.get_item_name(&pool_config.character2);
-                    print!("{} ", name);
-                }
-                println!();
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,414 @@
+use super::{BannerChoice, GachaSnapshot, SnapshotTree, MULTI_PULL};
+use crate::gacha_impl::*;
+use crate::gacha_traits::gacha_enums::*;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::time::{Duration, Instant};
+
+const COLOR_5STAR: Color = Color::Rgb(186, 106, 53);
+const COLOR_4STAR: Color = Color::Rgb(160, 90, 215);
+const COLOR_3STAR: Color = Color::Rgb(90, 150, 210);
+const REVEAL_INTERVAL: Duration = Duration::from_millis(150);
+
+struct HistoryEntry {
+    banner: BannerChoice,
+    level: ItemLevel,
+    is_up: bool,
+    name: String,
+    /// Pulls since the previous 5 star on this banner, counting this one
+    pity: u32,
+    /// Whether the item was exchanged for spark points rather than pulled
+    exchanged: bool,
+}
+
+struct App<'a> {
+    pool_config: &'a Pools,
+    gacha: GachaSnapshot,
+    snapshots: SnapshotTree,
+    /// Length of `history` when each snapshot was taken, indexed by snapshot id
+    history_marks: Vec<usize>,
+    dirty: bool,
+    history: Vec<HistoryEntry>,
+    history_scroll: usize,
+    /// Number of entries at the end of `history` belonging to the last multi-pull
+    reveal_len: usize,
+    revealed: usize,
+    last_reveal: Instant,
+    message: String,
+}
+
+impl<'a> App<'a> {
+    fn new(pool_config: &'a Pools) -> App<'a> {
+        let gacha = GachaSnapshot::new(pool_config);
+        App {
+            pool_config,
+            snapshots: SnapshotTree::new(gacha.clone()),
+            gacha,
+            history_marks: vec![0],
+            dirty: false,
+            history: Vec::new(),
+            history_scroll: 0,
+            reveal_len: 0,
+            revealed: 0,
+            last_reveal: Instant::now(),
+            message: String::new(),
+        }
+    }
+
+    /// Pulls once, followed by the automatic spark exchange the pull reaches if any
+    fn pull_one(&mut self) -> Vec<HistoryEntry> {
+        let banner = self.gacha.banner;
+        let gacha = self.gacha.active();
+        let pity = gacha.state().since_last_5star + 1;
+        let pulled = gacha.pull();
+        let exchanged = gacha.auto_exchange();
+        [Some((pulled, false)), exchanged.map(|result| (result, true))].into_iter().flatten()
+            .map(|(result, exchanged)| HistoryEntry {
+                banner,
+                level: result.get_item_level(),
+                is_up: result.is_up(),
+                name: result.pick_item_name(banner.pool(self.pool_config)).to_string(),
+                pity,
+                exchanged,
+            })
+            .collect()
+    }
+
+    fn pull(&mut self, num: usize) {
+        let remaining = self.gacha.active().remaining().map_or(num, |remaining| remaining as usize);
+        let num = num.min(remaining);
+        if num == 0 {
+            self.message = String::from("已达到本卡池的祈愿次数上限");
+            return;
+        }
+        let start = self.history.len();
+        for _ in 0..num {
+            let entries = self.pull_one();
+            self.history.extend(entries);
+        }
+        self.reveal_len = self.history.len() - start;
+        self.revealed = 0;
+        self.last_reveal = Instant::now();
+        self.history_scroll = 0;
+        self.dirty = true;
+        let rules = &self.gacha.current().rules;
+        self.message = match rules.pricing {
+            Some(_) => format!("消耗 {} 祈愿之缘", rules.cost(num as u32)),
+            None => String::new(),
+        };
+    }
+
+    fn tick(&mut self) {
+        if self.revealed < self.reveal_len && self.last_reveal.elapsed() >= REVEAL_INTERVAL {
+            self.revealed += 1;
+            self.last_reveal = Instant::now();
+        }
+    }
+
+    fn switch_banner(&mut self, offset: usize) {
+        let banners = self.gacha.banners();
+        let index = banners.iter().position(|b| *b == self.gacha.banner).unwrap_or(0);
+        self.gacha.banner = banners[(index + offset) % banners.len()];
+        self.reveal_len = 0;
+    }
+
+    /// Cycles the path through no path and each featured item in turn
+    fn cycle_path(&mut self) {
+        let (banner, choice) = self.gacha.charted();
+        let featured = banner.rules.star5.featured.as_ref().map_or(0, |featured| featured.count);
+        let next = match banner.state.want_5star_state {
+            WantState::None => Some(0),
+            WantState::Want(index, _) if index + 1 < featured => Some(index + 1),
+            WantState::Want(_, _) => None,
+        };
+        banner.chart_path(next);
+        self.message = match next {
+            Some(index) => format!("已定轨 {}", choice.pool(self.pool_config).get_up_5star_name(index)),
+            None => String::from("已取消定轨"),
+        };
+    }
+
+    fn snapshot(&mut self) {
+        let id = self.snapshots.push(format!("快照 {}", self.snapshots.nodes.len()), self.gacha.clone());
+        self.history_marks.push(self.history.len());
+        self.dirty = false;
+        self.message = format!("已保存快照 [{}]", id);
+    }
+
+    fn undo(&mut self) {
+        let target = if self.dirty {
+            Some(self.snapshots.current)
+        } else {
+            self.snapshots.nodes[self.snapshots.current].parent
+        };
+        let Some(id) = target else {
+            self.message = String::from("已经是初始状态");
+            return;
+        };
+        if let Some(snapshot) = self.snapshots.checkout(id) {
+            self.gacha = snapshot;
+            self.history.truncate(self.history_marks[id]);
+            self.dirty = false;
+            self.reveal_len = 0;
+            self.history_scroll = 0;
+            self.message = format!("已回到快照 [{}] {}", id, self.snapshots.nodes[id].label);
+        }
+    }
+
+    /// Handles a key press, returning false when the user asks to quit
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::Right => self.switch_banner(1),
+            KeyCode::BackTab | KeyCode::Left => self.switch_banner(self.gacha.banners().len() - 1),
+            KeyCode::Char('1') => self.pull(1),
+            KeyCode::Char('0') | KeyCode::Char(' ') | KeyCode::Enter => self.pull(10),
+            KeyCode::Char('p') => self.cycle_path(),
+            KeyCode::Char('s') => self.snapshot(),
+            KeyCode::Char('u') => self.undo(),
+            KeyCode::Char('r') => self.revealed = self.reveal_len,
+            KeyCode::Up => self.history_scroll = self.history_scroll.saturating_add(1),
+            KeyCode::Down => self.history_scroll = self.history_scroll.saturating_sub(1),
+            KeyCode::PageUp => self.history_scroll = self.history_scroll.saturating_add(10),
+            KeyCode::PageDown => self.history_scroll = self.history_scroll.saturating_sub(10),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [tabs_area, body_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [status_area, reveal_area, history_area] = Layout::horizontal([
+            Constraint::Length(36),
+            Constraint::Min(30),
+            Constraint::Length(32),
+        ]).areas(body_area);
+
+        self.draw_tabs(frame, tabs_area);
+        self.draw_status(frame, status_area);
+        self.draw_reveal(frame, reveal_area);
+        self.draw_history(frame, history_area);
+
+        let help = if self.message.is_empty() {
+            String::from("Tab/←→ 切换卡池  1 单抽  空格 十连  p 定轨  s 快照  u 回退  r 跳过动画  ↑↓ 历史  q 退出")
+        } else {
+            self.message.clone()
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::new().dim()), help_area);
+    }
+
+    fn draw_tabs(&self, frame: &mut Frame, area: Rect) {
+        let banners = self.gacha.banners();
+        let titles = banners.iter().map(|b| b.name());
+        let selected = banners.iter().position(|b| *b == self.gacha.banner);
+        let tabs = Tabs::new(titles)
+            .select(selected)
+            .highlight_style(Style::new().fg(COLOR_5STAR).add_modifier(Modifier::BOLD))
+            .block(Block::bordered().title(" Genshin Impact Gacha Simulator "));
+        frame.render_widget(tabs, area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let [gauge5_area, gauge4_area, guarantee_area, odds_area, stats_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Length(6),
+            Constraint::Min(0),
+        ]).areas(area);
+
+        let banner = self.gacha.current();
+        let since_5star = banner.state.since_last_5star;
+        let soft_pity_5star = banner.rules.star5.pity.soft_pity();
+        let max_5star = banner.rules.star5.pity.max;
+        let since_4star = banner.state.since_last_4star;
+        let max_4star = banner.rules.star4.pity.max;
+
+        let gauge_color = if since_5star + 1 >= soft_pity_5star { Color::Yellow } else { Color::Green };
+        let gauge5 = Gauge::default()
+            .block(Block::bordered().title(" 5★ 保底 "))
+            .gauge_style(Style::new().fg(gauge_color))
+            .ratio((since_5star as f64 / max_5star as f64).min(1.))
+            .label(format!("{}/{}  软保底 {}", since_5star, max_5star, soft_pity_5star));
+        frame.render_widget(gauge5, gauge5_area);
+
+        let gauge4 = Gauge::default()
+            .block(Block::bordered().title(" 4★ 保底 "))
+            .gauge_style(Style::new().fg(COLOR_4STAR))
+            .ratio((since_4star as f64 / max_4star as f64).min(1.))
+            .label(format!("{}/{}", since_4star, max_4star));
+        frame.render_widget(gauge4, gauge4_area);
+
+        let mut guarantee = Vec::new();
+        let up_probability = banner.rules.star5.featured.as_ref()
+            .map_or(0., |featured| featured.up_probability(banner.state.lost_5star_streak));
+        let radiance = banner.rules.star5.featured.as_ref()
+            .and_then(|featured| featured.radiance.as_ref())
+            .map(|_| banner.state.lost_5star_streak);
+        guarantee.push(guarantee_line(banner.state.last_5star_is_up, up_probability, radiance));
+        guarantee.push(match (&banner.rules.path, banner.state.want_5star_state) {
+            (Some(path), WantState::Want(index, points)) => Line::from(format!(
+                "定轨 {}  命定值 {}/{}",
+                self.gacha.banner.pool(self.pool_config).get_up_5star_name(index),
+                points,
+                path.max_fate_points,
+            )),
+            (Some(_), WantState::None) => Line::from("未定轨"),
+            (None, _) => match &banner.rules.spark {
+                Some(spark) => Line::from(format!("兑换点数 {}/{}", banner.state.spark_points, spark.threshold)),
+                None => Line::from(""),
+            },
+        });
+        guarantee.push(Line::from(if banner.state.last_4star_is_up { "4★ 小保底" } else { "4★ 大保底" }));
+        frame.render_widget(Paragraph::new(guarantee).block(Block::bordered().title(" 保底状态 ")), guarantee_area);
+
+        let odds = banner.odds(MULTI_PULL);
+        let mut next = vec![
+            Line::from(format!("单抽 5★ {:.2}%  4★ {:.2}%", odds.next_5star * 100., odds.next_4star * 100.)),
+            Line::from(format!("十连 5★ {:.2}%  4★ {:.2}%", odds.multi_5star * 100., odds.multi_4star * 100.)),
+        ];
+        if let Some(up_5star) = odds.up_5star {
+            next.push(Line::from(format!("下一个 5★ UP 概率 {:.2}%", up_5star * 100.)));
+        }
+        next.push(Line::from(match odds.to_soft_pity {
+            Some(0) => format!("已进入软保底  距硬保底 {}", odds.to_hard_pity),
+            Some(pulls) => format!("距软保底 {}  距硬保底 {}", pulls, odds.to_hard_pity),
+            None => format!("距硬保底 {}", odds.to_hard_pity),
+        }));
+        frame.render_widget(Paragraph::new(next).block(Block::bordered().title(" 下一抽 ")), odds_area);
+
+        let entries = self.history.iter().filter(|e| e.banner == self.gacha.banner);
+        let mut pulls = 0u32;
+        let mut count_5star = 0u32;
+        let mut up_5star = 0u32;
+        let mut count_4star = 0u32;
+        let mut pity_sum = 0u32;
+        for entry in entries.filter(|e| !e.exchanged) {
+            pulls += 1;
+            match entry.level {
+                ItemLevel::Star5 => {
+                    count_5star += 1;
+                    pity_sum += entry.pity;
+                    if entry.is_up {
+                        up_5star += 1;
+                    }
+                }
+                ItemLevel::Star4 => count_4star += 1,
+                ItemLevel::Star3 => {}
+            }
+        }
+        let average = if count_5star > 0 { format!("{:.1}", pity_sum as f64 / count_5star as f64) } else { String::from("-") };
+        let stats = vec![
+            Line::from(format!("总抽数    {}", pulls)),
+            Line::from(vec![Span::raw("5★        "), Span::styled(count_5star.to_string(), Style::new().fg(COLOR_5STAR))]),
+            Line::from(format!("UP 5★     {}", up_5star)),
+            Line::from(vec![Span::raw("4★        "), Span::styled(count_4star.to_string(), Style::new().fg(COLOR_4STAR))]),
+            Line::from(format!("平均出金  {}", average)),
+        ];
+        frame.render_widget(Paragraph::new(stats).block(Block::bordered().title(" 统计 ")), stats_area);
+    }
+
+    fn draw_reveal(&self, frame: &mut Frame, area: Rect) {
+        let batch = &self.history[self.history.len() - self.reveal_len..];
+        let best = batch[..self.revealed].iter().map(|e| e.level).fold(None, |best, level| match (best, level) {
+            (_, ItemLevel::Star5) | (Some(ItemLevel::Star5), _) => Some(ItemLevel::Star5),
+            (_, ItemLevel::Star4) | (Some(ItemLevel::Star4), _) => Some(ItemLevel::Star4),
+            _ => Some(ItemLevel::Star3),
+        });
+        let border = best.map(level_color).unwrap_or(Color::Reset);
+
+        let lines: Vec<Line> = batch.iter().enumerate().map(|(i, entry)| {
+            if i < self.revealed {
+                entry_line(entry)
+            } else {
+                Line::from("  ✦").dim()
+            }
+        }).collect();
+        let block = Block::bordered()
+            .title(format!(" {} ", self.gacha.banner.name()))
+            .border_style(Style::new().fg(border));
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_history(&self, frame: &mut Frame, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let max_scroll = self.history.len().saturating_sub(visible);
+        let scroll = self.history_scroll.min(max_scroll);
+        let items: Vec<ListItem> = self.history.iter().rev()
+            .skip(scroll)
+            .take(visible)
+            .map(|entry| ListItem::new(entry_line(entry)))
+            .collect();
+        let title = format!(" 历史 {}/{} ", self.history.len() - scroll, self.history.len());
+        frame.render_widget(List::new(items).block(Block::bordered().title(title)), area);
+    }
+}
+
+fn level_color(level: ItemLevel) -> Color {
+    match level {
+        ItemLevel::Star5 => COLOR_5STAR,
+        ItemLevel::Star4 => COLOR_4STAR,
+        ItemLevel::Star3 => COLOR_3STAR,
+    }
+}
+
+fn entry_line(entry: &HistoryEntry) -> Line<'static> {
+    let stars = match entry.level {
+        ItemLevel::Star5 => "★★★★★",
+        ItemLevel::Star4 => "★★★★ ",
+        ItemLevel::Star3 => "★★★  ",
+    };
+    let mut spans = vec![
+        Span::styled(format!("  {} ", stars), Style::new().fg(level_color(entry.level))),
+        Span::styled(entry.name.clone(), Style::new().fg(level_color(entry.level)).add_modifier(if entry.level == ItemLevel::Star3 { Modifier::empty() } else { Modifier::BOLD })),
+    ];
+    if entry.exchanged {
+        spans.push(Span::raw(" (兑换)").dim());
+    } else if entry.level == ItemLevel::Star5 {
+        spans.push(Span::raw(format!(" ({})", entry.pity)).dim());
+    }
+    Line::from(spans)
+}
+
+fn guarantee_line(last_is_up: bool, up_probability: f64, lost_streak: Option<u32>) -> Line<'static> {
+    if last_is_up {
+        match lost_streak {
+            Some(lost_streak) => Line::from(format!("5★ 小保底  UP 概率 {}%  明光计数 {}", up_probability * 100., lost_streak)),
+            None => Line::from(format!("5★ 小保底  UP 概率 {}%", up_probability * 100.)),
+        }
+    } else {
+        Line::from("5★ 大保底  下一个 5★ 必定 UP").fg(COLOR_5STAR)
+    }
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if event::poll(Duration::from_millis(30))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+        app.tick();
+    }
+}
+
+pub fn run(pool_config: &Pools) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut app = App::new(pool_config);
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}